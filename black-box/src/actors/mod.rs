@@ -1,14 +1,41 @@
-use std::{future::Future, sync::atomic::AtomicU64};
+use std::{
+    fmt,
+    future::Future,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
 
 use async_channel::{Sender, WeakSender};
 
 use crate::{
+    coalesce::Reservation,
+    dead_letter::DeadLetterReason,
+    error::AskError,
     executor::Context,
+    futures::race_biased,
+    headers::Headers,
+    mailbox::Mailbox,
     message::{Envelope, Message},
+    request::{Answer, Request, RequestHandler, Responder},
+    schedule::{Delivery, ScheduleHandle},
 };
 
 static ADDRESS_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+pub(crate) fn next_address_id() -> u64 {
+    ADDRESS_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Identifies an actor, shared by every address of that actor and its executor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActorId(pub(crate) u64);
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor-{}", self.0)
+    }
+}
+
 /// Abstraction for message handleing
 ///
 /// Actors are spawned in an [`Executor`](crate::Executor), and run in the executor's event loop.
@@ -22,6 +49,18 @@ pub trait Actor: Sized {
     fn stopping(&mut self, _ctx: &Context<Self>) -> impl Future<Output = ()> + Send {
         std::future::ready(())
     }
+
+    /// Invoked when a handler exceeds its deadline and is cancelled by the executor
+    ///
+    /// `message` is the type name of the message whose handler was cancelled. See
+    /// [`Executor::set_handler_timeout`](crate::Executor::set_handler_timeout).
+    fn on_timeout(
+        &mut self,
+        _message: &'static str,
+        _ctx: &Context<Self>,
+    ) -> impl Future<Output = ()> + Send {
+        std::future::ready(())
+    }
 }
 
 /// The implementation for how an actor handles a particular message
@@ -43,13 +82,13 @@ where
 /// runtimes, etc.
 #[derive(Debug)]
 pub struct Address<A> {
-    id: u64,
     sender: Sender<Envelope<A>>,
+    mailbox: Arc<Mailbox>,
 }
 
 impl<A> PartialEq for Address<A> {
     fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+        self.id() == other.id()
     }
 }
 
@@ -65,21 +104,129 @@ impl<A> Clone for Address<A> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
-            id: self.id,
+            mailbox: self.mailbox.clone(),
         }
     }
 }
 
 impl<A> Address<A> {
-    pub(crate) fn new(sender: Sender<Envelope<A>>) -> Self {
-        let id = ADDRESS_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    pub(crate) fn new(sender: Sender<Envelope<A>>, mailbox: Arc<Mailbox>) -> Self {
+        Self { sender, mailbox }
+    }
+
+    /// The id of the actor this address sends to
+    pub fn id(&self) -> ActorId {
+        self.mailbox.id()
+    }
+
+    pub(crate) fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    /// The number of messages counted against the mailbox capacity
+    ///
+    /// This includes messages the executor has already taken from the mailbox but not yet handled,
+    /// such as those passed over by a batch. Messages held back by [`Self::send_after`] or stashed
+    /// by the actor are not counted, as they do not take up capacity, so this is not the total
+    /// number of messages still to be handled.
+    pub fn len(&self) -> usize {
+        self.mailbox.len()
+    }
 
-        Self { sender, id }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of messages the mailbox can hold, `None` if it is unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.mailbox.capacity()
+    }
+
+    /// Whether sends will wait for capacity, or be dropped by [`Self::try_send`]
+    ///
+    /// This compares [`Self::len`] with the capacity, so scheduled and stashed messages never make
+    /// the mailbox full.
+    pub fn is_full(&self) -> bool {
+        self.mailbox.is_full()
+    }
+
+    /// Whether the mailbox no longer accepts messages
+    ///
+    /// This is the case once [`Self::close`] has been called, or the executor has stopped or been
+    /// dropped.
+    pub fn is_closed(&self) -> bool {
+        self.mailbox.is_closed() || self.sender.is_closed()
+    }
+
+    /// The number of live addresses to the actor, including this one
+    ///
+    /// [`WeakAddress`]es, such as the one held by the actor's [`Context`], are not counted.
+    pub fn address_count(&self) -> usize {
+        self.sender.sender_count()
+    }
+
+    /// Stop accepting new messages, without stopping the actor
+    ///
+    /// Messages already in the mailbox are still handled, along with any held back by
+    /// [`Self::send_after`] or stashed. Later sends from any address to the actor are reported as
+    /// a [`DeadLetter`](crate::DeadLetter). The actor keeps running until it shuts down, or every
+    /// address has been dropped.
+    pub fn close(&self) {
+        self.mailbox.close();
+    }
+
+    /// Enqueue an already packed envelope, handing it back if the mailbox is closed
+    ///
+    /// Scheduled envelopes are held by the executor rather than queued, so they are enqueued
+    /// without waiting for capacity.
+    pub(crate) async fn post(&self, env: Envelope<A>) -> Result<(), Envelope<A>> {
+        if self.mailbox.is_closed() {
+            return Err(env);
+        }
+
+        let Some((mut env, reservation)) = self.offer(env) else {
+            return Ok(());
+        };
+
+        if !env.is_scheduled() && !self.mailbox.acquire(&mut env).await {
+            return reservation.restore(env).map_or(Ok(()), Err);
+        }
+
+        self.enqueue(env, reservation)
+    }
+
+    /// Offer the envelope for coalescing, stamped now as it may replace content already enqueued
+    ///
+    /// An envelope which has to be enqueued is stamped again once it is.
+    fn offer(&self, mut env: Envelope<A>) -> Option<(Envelope<A>, Reservation<'_>)> {
+        env.stamp(self.mailbox.now());
+        self.mailbox.coalescing.offer(env)
+    }
+
+    /// Push an envelope which has already been counted against the capacity onto the channel
+    fn enqueue(
+        &self,
+        mut env: Envelope<A>,
+        reservation: Reservation<'_>,
+    ) -> Result<(), Envelope<A>> {
+        let now = self.mailbox.now();
+        env.stamp(now);
+        match self.sender.try_send(env) {
+            Ok(()) => {
+                reservation.commit(now);
+                Ok(())
+            }
+            Err(err) => {
+                let mut env = err.into_inner();
+                self.mailbox.release(&mut env);
+                reservation.restore(env).map_or(Ok(()), Err)
+            }
+        }
     }
 
     pub fn downgrade(&self) -> WeakAddress<A> {
         let sender = self.sender.downgrade();
-        WeakAddress::new(self.id, sender)
+        WeakAddress::new(sender, self.mailbox.clone())
     }
 }
 
@@ -89,7 +236,8 @@ where
 {
     /// Send the given message to the actor's receiver.
     ///
-    /// If the receiver is currently full, it will await capacity to enqueue the message
+    /// If the receiver is currently full, it will await capacity to enqueue the message. If the
+    /// mailbox is closed the message is reported as a [`DeadLetter`](crate::DeadLetter).
     pub async fn send<M>(&self, message: M)
     where
         A: Handler<M>,
@@ -97,19 +245,195 @@ where
     {
         let env = Envelope::pack(message);
 
-        // TODO: Decide what to do here
-        let _ = self.sender.send(env).await;
+        if let Err(env) = self.post(env).await {
+            self.mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the given message along with `headers`, which its handler can read with
+    /// [`Context::headers`]
+    ///
+    /// The enqueue timestamp of `headers` is set to the time of sending.
+    pub async fn send_with_headers<M>(&self, message: M, headers: Headers)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        let mut env = Envelope::pack(message);
+        env.set_headers(headers.inherit());
+
+        if let Err(env) = self.post(env).await {
+            self.mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
     }
 
+    /// Send the given message to the actor's receiver, blocking the current thread until there is
+    /// capacity to enqueue it
+    ///
+    /// This allows non-async code to post messages, it must not be called from within an async
+    /// context.
+    pub fn send_blocking<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        let env = Envelope::pack(message);
+        if self.mailbox.is_closed() {
+            return self
+                .mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+
+        let Some((mut env, reservation)) = self.offer(env) else {
+            return;
+        };
+
+        if !self.mailbox.acquire_blocking(&mut env) {
+            if let Some(env) = reservation.restore(env) {
+                self.mailbox
+                    .dead_letter(env, DeadLetterReason::MailboxClosed);
+            }
+            return;
+        }
+
+        if let Err(env) = self.enqueue(env, reservation) {
+            self.mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the given message only if there is capacity to enqueue it immediately
+    ///
+    /// A message which cannot be enqueued is reported as a [`DeadLetter`](crate::DeadLetter).
     pub fn try_send<M>(&self, message: M)
     where
         A: Handler<M>,
         M: Message,
     {
         let env = Envelope::pack(message);
+        if self.mailbox.is_closed() {
+            return self
+                .mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+
+        let Some((mut env, reservation)) = self.offer(env) else {
+            return;
+        };
+
+        if !self.mailbox.try_acquire(&mut env) {
+            if let Some(env) = reservation.restore(env) {
+                self.mailbox.dead_letter(env, DeadLetterReason::MailboxFull);
+            }
+            return;
+        }
+
+        if let Err(env) = self.enqueue(env, reservation) {
+            self.mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the request to the actor, and await its response
+    ///
+    /// If the receiver is currently full, it will await capacity to enqueue the request.
+    pub async fn ask<M>(&self, message: M) -> Result<M::Response, AskError>
+    where
+        A: RequestHandler<M>,
+        M: Request,
+    {
+        self.request(message).await?.recv().await
+    }
+
+    /// Enqueue the request, returning where its response will arrive
+    pub(crate) async fn request<M>(&self, message: M) -> Result<Answer<M::Response>, AskError>
+    where
+        A: RequestHandler<M>,
+        M: Request,
+    {
+        let (responder, answer) = Responder::pair();
+        let env = Envelope::pack_request(message, responder);
+        self.post(env).await.map_err(|_| AskError::MailboxClosed)?;
+        Ok(answer)
+    }
+
+    /// Send the request to the actor, and await its response for at most `timeout`
+    ///
+    /// The timeout covers both waiting for capacity in the mailbox, failing with
+    /// [`AskError::MailboxFull`], and waiting for the response, failing with [`AskError::Timeout`].
+    /// It is measured with the executor's [`Timer`](crate::Timer), see
+    /// [`Executor::set_timer`](crate::Executor::set_timer).
+    pub async fn ask_timeout<M>(
+        &self,
+        message: M,
+        timeout: Duration,
+    ) -> Result<M::Response, AskError>
+    where
+        A: RequestHandler<M>,
+        M: Request,
+    {
+        let mut sleep = self.mailbox.timer().sleep(timeout);
+        let (responder, answer) = Responder::pair();
+        let env = Envelope::pack_request(message, responder);
+
+        let post = async { self.post(env).await.map_err(|_| AskError::MailboxClosed) };
+        let full = async {
+            (&mut sleep).await;
+            Err(AskError::MailboxFull)
+        };
+        race_biased(post, full).await?;
+
+        let recv = answer.recv();
+        let timed_out = async {
+            sleep.await;
+            Err(AskError::Timeout)
+        };
+        race_biased(recv, timed_out).await
+    }
+
+    /// Deliver the message to the actor once `delay` has elapsed
+    ///
+    /// The message is enqueued immediately and held by the executor until it is due, so no task
+    /// is spawned and no address is kept alive while waiting. The delay is measured with the
+    /// executor's [`Timer`](crate::Timer) from the moment of sending. Scheduled messages do not
+    /// take up mailbox capacity, so this never waits.
+    pub async fn send_after<M>(&self, message: M, delay: Duration) -> ScheduleHandle
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        let deadline = self.mailbox.timer().now() + delay;
+        self.schedule(message, deadline).await
+    }
+
+    /// Deliver the message to the actor once `deadline` has been reached
+    ///
+    /// See [`Self::send_after`].
+    pub async fn send_at<M>(&self, message: M, deadline: Instant) -> ScheduleHandle
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        self.schedule(message, deadline).await
+    }
+
+    async fn schedule<M>(&self, message: M, deadline: Instant) -> ScheduleHandle
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        let (delivery, handle) = Delivery::new(deadline);
+        let env = Envelope::pack(message).schedule(delivery);
 
-        // TODO: Decide what to do here
-        let _ = self.sender.try_send(env);
+        // Scheduled envelopes are never coalesced
+        if let Err(env) = self.post(env).await {
+            self.mailbox
+                .dead_letter(env, DeadLetterReason::MailboxClosed);
+        }
+
+        handle
     }
 }
 
@@ -119,27 +443,75 @@ where
 /// runtimes, etc.
 #[derive(Debug)]
 pub struct WeakAddress<A> {
-    id: u64,
     sender: WeakSender<Envelope<A>>,
+    mailbox: Arc<Mailbox>,
 }
 
 impl<A> Clone for WeakAddress<A> {
     fn clone(&self) -> Self {
         Self {
-            id: self.id,
             sender: self.sender.clone(),
+            mailbox: self.mailbox.clone(),
         }
     }
 }
 
 impl<A> WeakAddress<A> {
-    pub(crate) fn new(id: u64, sender: WeakSender<Envelope<A>>) -> Self {
-        Self { id, sender }
+    pub(crate) fn new(sender: WeakSender<Envelope<A>>, mailbox: Arc<Mailbox>) -> Self {
+        Self { sender, mailbox }
+    }
+
+    /// The id of the actor this address sends to
+    pub fn id(&self) -> ActorId {
+        self.mailbox.id()
+    }
+
+    pub(crate) fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    /// See [`Address::len`], this is `0` once every address has been dropped
+    pub fn len(&self) -> usize {
+        self.upgrade().map_or(0, |address| address.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// See [`Address::capacity`]
+    pub fn capacity(&self) -> Option<usize> {
+        self.upgrade().and_then(|address| address.capacity())
+    }
+
+    /// See [`Address::is_full`]
+    pub fn is_full(&self) -> bool {
+        self.upgrade().is_some_and(|address| address.is_full())
+    }
+
+    /// See [`Address::is_closed`], this is also the case once every address has been dropped
+    pub fn is_closed(&self) -> bool {
+        self.upgrade().map_or(true, |address| address.is_closed())
+    }
+
+    /// The number of live addresses to the actor
+    ///
+    /// This is approximate, the count is taken by briefly upgrading this address, so a concurrent
+    /// upgrade or drop of another weak address may be seen as an extra or missing address.
+    pub fn address_count(&self) -> usize {
+        // Discount the address upgraded to take the count
+        self.upgrade()
+            .map_or(0, |address| address.address_count() - 1)
+    }
+
+    /// See [`Address::close`]
+    pub fn close(&self) {
+        self.mailbox.close();
     }
 
     pub fn upgrade(&self) -> Option<Address<A>> {
         let sender = self.sender.upgrade()?;
-        Some(Address::new(sender))
+        Some(Address::new(sender, self.mailbox.clone()))
     }
 }
 
@@ -166,6 +538,40 @@ mod test {
         async fn handle(&mut self, _msg: Msg, _ctx: &Context<Self>) {}
     }
 
+    #[tokio::test]
+    async fn mailbox_introspection() {
+        let (mut executor, address) = Executor::new_with_capacity(Act, 2);
+        let weak = address.downgrade();
+        assert_eq!(address.capacity(), Some(2));
+        assert_eq!(weak.address_count(), 1);
+
+        let other = address.clone();
+        other.send(Msg).await;
+        other.send(Msg).await;
+        assert_eq!(weak.len(), 2);
+        assert!(address.is_full());
+        assert_eq!(address.address_count(), 2);
+
+        let dead = Arc::new(Mutex::new(0));
+        let count = dead.clone();
+        executor.set_dead_letters(crate::DeadLetters::new(move |_| {
+            *count.lock().unwrap() += 1
+        }));
+        weak.close();
+        assert!(other.is_closed());
+        other.try_send(Msg);
+        assert_eq!(*dead.lock().unwrap(), 1);
+
+        // Queued messages are still handled, and the actor keeps running
+        let idle = tokio::time::sleep(Duration::from_millis(20));
+        assert_eq!(executor.run_against(idle).await, Ok(true));
+        assert!(address.is_empty());
+
+        drop((address, other));
+        assert_eq!(weak.address_count(), 0);
+        assert!(weak.is_closed());
+    }
+
     #[test]
     fn partial_eq_on_clone() {
         let (_executor, address) = Executor::new(Act);
@@ -0,0 +1,274 @@
+use std::{any::TypeId, collections::HashMap, future::Future, pin::Pin};
+
+use crate::{
+    executor::Context,
+    message::{Envelope, Message},
+    Handler,
+};
+
+/// Handle every queued message of a type at once
+///
+/// Once batching is enabled for `M` with
+/// [`Executor::set_batch_limit`](crate::Executor::set_batch_limit), dequeuing an `M` also pulls
+/// any further `M`s already waiting in the mailbox, up to the limit, and hands them all to
+/// [`BatchHandler::handle_batch`] in the order they were sent. Messages of other types which are
+/// passed over stay queued, and are handled afterwards in their original order.
+///
+/// Messages are only ever batched with those already waiting, the executor never holds a message
+/// back in the hope of filling a batch. [`Handler::handle`] is still used for `M` until batching is
+/// enabled.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Insert(u64);
+///
+/// #[derive(Default)]
+/// struct Writer {
+///     transactions: usize,
+/// }
+///
+/// impl Actor for Writer {}
+///
+/// impl Handler<Insert> for Writer {
+///     async fn handle(&mut self, msg: Insert, ctx: &Context<Self>) {
+///         self.handle_batch(vec![msg], ctx).await
+///     }
+/// }
+///
+/// impl BatchHandler<Insert> for Writer {
+///     async fn handle_batch(&mut self, msgs: Vec<Insert>, _ctx: &Context<Self>) {
+///         // Write every row in a single transaction
+///         self.transactions += 1;
+///     }
+/// }
+///
+/// let (mut executor, addr) = Executor::new(Writer::default());
+/// executor.set_batch_limit::<Insert>(64);
+/// ```
+pub trait BatchHandler<M>: Handler<M>
+where
+    M: Message,
+{
+    /// Asynchronously act on a non-empty batch of messages, with mutable access to self
+    fn handle_batch(
+        &mut self,
+        msgs: Vec<M>,
+        ctx: &Context<Self>,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+type BatchFn<A> = for<'a> fn(
+    &'a mut A,
+    Vec<Envelope<A>>,
+    &'a Context<A>,
+) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+fn handle_batch<'a, A, M>(
+    actor: &'a mut A,
+    envelopes: Vec<Envelope<A>>,
+    ctx: &'a Context<A>,
+) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>
+where
+    A: BatchHandler<M> + Send,
+    M: Message,
+{
+    let msgs = envelopes.into_iter().map(Envelope::into_message).collect();
+    Box::pin(actor.handle_batch(msgs, ctx))
+}
+
+/// How a single message type is batched
+pub(crate) struct Batch<A> {
+    pub(crate) limit: usize,
+    pub(crate) handle: BatchFn<A>,
+}
+
+impl<A> Clone for Batch<A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for Batch<A> {}
+
+/// The message types an executor batches, keyed by [`TypeId`]
+pub(crate) struct Batches<A>(HashMap<TypeId, Batch<A>>);
+
+impl<A> Default for Batches<A> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<A> std::fmt::Debug for Batches<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Batches")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+impl<A> Batches<A> {
+    pub(crate) fn insert<M>(&mut self, limit: usize)
+    where
+        A: BatchHandler<M> + Send,
+        M: Message,
+    {
+        let batch = Batch {
+            limit: limit.max(1),
+            handle: handle_batch::<A, M>,
+        };
+        self.0.insert(TypeId::of::<M>(), batch);
+    }
+
+    pub(crate) fn get(&self, id: TypeId) -> Option<Batch<A>> {
+        self.0.get(&id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Actor, Executor, Request, RequestHandler};
+
+    use super::*;
+
+    struct Insert(usize);
+    struct Flush;
+    struct Stop;
+
+    #[derive(Default)]
+    struct Writer {
+        batches: Vec<Vec<usize>>,
+        log: Vec<&'static str>,
+        /// The mailbox length seen by each batch
+        waiting: Vec<usize>,
+    }
+
+    impl Actor for Writer {}
+
+    impl Handler<Insert> for Writer {
+        async fn handle(&mut self, msg: Insert, ctx: &Context<Self>) {
+            self.handle_batch(vec![msg], ctx).await
+        }
+    }
+
+    impl BatchHandler<Insert> for Writer {
+        async fn handle_batch(&mut self, msgs: Vec<Insert>, ctx: &Context<Self>) {
+            self.waiting.push(ctx.address().len());
+            self.batches
+                .push(msgs.into_iter().map(|msg| msg.0).collect());
+            self.log.push("insert");
+        }
+    }
+
+    impl Handler<Flush> for Writer {
+        async fn handle(&mut self, _msg: Flush, _ctx: &Context<Self>) {
+            self.log.push("flush");
+        }
+    }
+
+    #[tokio::test]
+    async fn waiting_messages_are_batched_up_to_limit() {
+        let (mut executor, addr) = Executor::new(Writer::default());
+        executor.set_batch_limit::<Insert>(3);
+        for i in 0..5 {
+            addr.send(Insert(i)).await;
+        }
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(
+            executor.actor_ref().batches,
+            vec![vec![0, 1, 2], vec![3, 4]]
+        );
+    }
+
+    #[tokio::test]
+    async fn passed_over_messages_keep_their_order() {
+        let (mut executor, addr) = Executor::new(Writer::default());
+        executor.set_batch_limit::<Insert>(10);
+        addr.send(Flush).await;
+        addr.send(Insert(0)).await;
+        addr.send(Flush).await;
+        addr.send(Insert(1)).await;
+        addr.send(Flush).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        let actor = executor.actor_ref();
+        assert_eq!(actor.batches, vec![vec![0, 1]]);
+        assert_eq!(actor.log, vec!["flush", "insert", "flush", "flush"]);
+    }
+
+    impl Handler<Stop> for Writer {
+        async fn handle(&mut self, _msg: Stop, ctx: &Context<Self>) {
+            ctx.shutdown();
+        }
+    }
+
+    #[tokio::test]
+    async fn passed_over_messages_keep_taking_capacity() {
+        let (mut executor, addr) = Executor::new_with_capacity(Writer::default(), 4);
+        executor.set_batch_limit::<Insert>(10);
+        addr.send(Insert(0)).await;
+        addr.send(Flush).await;
+        addr.send(Insert(1)).await;
+        addr.send(Stop).await;
+
+        assert!(executor.run().await.is_ok());
+        // The flush and stop were moved aside for the batch, but still wait to be handled
+        assert_eq!(executor.actor_ref().waiting, [2]);
+        assert_eq!(executor.actor_ref().log, ["insert", "flush"]);
+    }
+
+    impl Request for Insert {
+        type Response = usize;
+    }
+
+    impl RequestHandler<Insert> for Writer {
+        async fn respond(&mut self, msg: Insert, _ctx: &Context<Self>) -> usize {
+            self.log.push("respond");
+            msg.0
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_are_not_batched() {
+        let (mut executor, addr) = Executor::new(Writer::default());
+        executor.set_batch_limit::<Insert>(10);
+        addr.send(Insert(0)).await;
+        let asks: Vec<_> = (1..=2)
+            .map(|i| {
+                let addr = addr.clone();
+                tokio::spawn(async move { addr.ask(Insert(i)).await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        addr.send(Insert(3)).await;
+        drop(addr);
+
+        let run = tokio::spawn(async move {
+            let _ = executor.run().await;
+            executor
+        });
+        for (i, ask) in (1..=2).zip(asks) {
+            assert_eq!(ask.await.unwrap(), Ok(i));
+        }
+        let executor = run.await.unwrap();
+        let actor = executor.actor_ref();
+        assert_eq!(actor.batches, vec![vec![0, 3]]);
+        assert_eq!(actor.log, ["insert", "respond", "respond"]);
+    }
+
+    #[tokio::test]
+    async fn without_a_limit_messages_are_handled_singly() {
+        let (mut executor, addr) = Executor::new(Writer::default());
+        addr.send(Insert(0)).await;
+        addr.send(Insert(1)).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().batches, vec![vec![0], vec![1]]);
+    }
+}
@@ -0,0 +1,268 @@
+use std::thread::JoinHandle;
+
+use async_channel::{Receiver, Sender};
+
+use crate::{
+    cancel::CancellationToken,
+    error::AddressError,
+    executor::{ShutdownHandle, State, DEFAULT_CAP},
+    message::SyncEnvelope,
+};
+
+use super::{SyncActor, SyncAddress, WeakSyncAddress};
+
+/// The [`Context`](crate::Context) counterpart for actors run on a [`SyncExecutor`]
+#[derive(Debug, Clone)]
+pub struct SyncContext<A> {
+    sender: Sender<State>,
+    address: WeakSyncAddress<A>,
+    token: CancellationToken,
+}
+
+impl<A> SyncContext<A> {
+    /// Triggers the end of the executor.
+    ///
+    /// Once triggered, no new messages will be processed and the actor will exit after calling
+    /// [`SyncActor::stopping`].
+    pub fn shutdown(&self) {
+        let _ = self.sender.force_send(State::Shutdown);
+        self.token.cancel();
+    }
+
+    /// Whether shutdown of the actor has been requested
+    ///
+    /// Long running handlers can periodically check this to bail out early.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// A cloneable token which can be handed to work spawned from within a handler
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Retrieve the address for the executor's actor
+    ///
+    /// This is useful when an actor wants to emit messages to itself.
+    pub fn address(&self) -> &WeakSyncAddress<A> {
+        &self.address
+    }
+}
+
+/// The event loop for a [`SyncActor`]
+///
+/// Runs an actor with synchronous handlers on a dedicated OS thread, which makes it suitable for
+/// CPU-bound work or blocking libraries that would otherwise starve an async runtime. The
+/// [`SyncAddress`] can be used from both async and blocking code.
+///
+/// Only the core event loop of the [`Executor`](crate::Executor) is provided. Handler timeouts,
+/// scheduled, stashed, coalesced and batched messages, middleware, snapshots, replies and headers
+/// are not supported, and undeliverable messages are only reported to the global
+/// [`DeadLetters`](crate::DeadLetters) sink.
+///
+/// # Example
+///
+/// ```no_run
+/// # use black_box::*;
+/// struct Sqlite;
+/// impl SyncActor for Sqlite {}
+///
+/// let (executor, addr) = SyncExecutor::new(Sqlite);
+/// let handle = executor.spawn();
+/// ```
+#[derive(Debug)]
+pub struct SyncExecutor<A> {
+    actor: A,
+    context: SyncContext<A>,
+    state: State,
+    from_context: Receiver<State>,
+    receiver: Receiver<SyncEnvelope<A>>,
+}
+
+impl<A> SyncExecutor<A> {
+    pub fn new(actor: A) -> (Self, SyncAddress<A>) {
+        Self::new_with_capacity(actor, DEFAULT_CAP)
+    }
+
+    pub fn new_with_capacity(actor: A, cap: usize) -> (Self, SyncAddress<A>) {
+        let (sender, receiver) = async_channel::bounded(cap);
+        let address = SyncAddress::new(sender);
+        let (state_tx, state_rx) = async_channel::unbounded();
+        let me = Self {
+            actor,
+            receiver,
+            context: SyncContext {
+                sender: state_tx,
+                address: address.downgrade(),
+                token: Default::default(),
+            },
+            from_context: state_rx,
+            state: Default::default(),
+        };
+
+        (me, address)
+    }
+
+    /// Construct a new shutdown handle to be able to remotely shutdown the actor
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.context.sender.clone(), self.context.token.clone())
+    }
+
+    pub fn actor_ref(&self) -> &A {
+        &self.actor
+    }
+
+    pub fn actor_mut(&mut self) -> &mut A {
+        &mut self.actor
+    }
+}
+
+enum Race<A> {
+    State(State),
+    Envelope(SyncEnvelope<A>),
+}
+
+impl<A> SyncExecutor<A>
+where
+    A: SyncActor,
+{
+    /// Runs the executor on the current thread, returns `Ok(())` if the actor invoked shutdown
+    /// manually, and `Err(_)` if all addresses to the actor have been dropped
+    ///
+    /// This blocks the current thread, see [`Self::spawn`] to run it on a dedicated one. A
+    /// shutdown requested before the executor runs is honored once it starts.
+    pub fn run(&mut self) -> Result<(), AddressError> {
+        self.state = State::Continue;
+        self.actor.starting(&self.context);
+
+        let result = loop {
+            match self.state {
+                State::Continue | State::Snapshot => self.continuation(),
+                State::Shutdown => break Ok(()),
+                State::SendersClosed => break Err(AddressError::Closed),
+            }
+        };
+
+        self.actor.stopping(&self.context);
+        self.reset_state();
+
+        result
+    }
+
+    /// Discards any state changes left over from a run, so the executor can be run again
+    fn reset_state(&mut self) {
+        while self.from_context.try_recv().is_ok() {}
+        self.context.token.reset();
+        self.state = State::Continue;
+    }
+
+    fn continuation(&mut self) {
+        let fut1 = async { self.from_context.recv().await.map(|val| Race::State(val)) };
+        let fut2 = async { self.receiver.recv().await.map(|val| Race::Envelope(val)) };
+
+        match crate::futures::block_on(crate::futures::race_biased(fut1, fut2)) {
+            Ok(Race::State(state)) => self.state = state,
+            Ok(Race::Envelope(env)) => env.resolve(&mut self.actor, &self.context),
+            Err(_) => {
+                self.state = State::SendersClosed;
+            }
+        }
+    }
+}
+
+impl<A> SyncExecutor<A>
+where
+    A: SyncActor + Send + 'static,
+{
+    /// Runs the executor on a newly spawned OS thread
+    ///
+    /// The executor is handed back once it exits, along with the result of [`Self::run`].
+    pub fn spawn(mut self) -> JoinHandle<(Self, Result<(), AddressError>)> {
+        std::thread::spawn(move || {
+            let result = self.run();
+            (self, result)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SyncHandler;
+
+    use super::*;
+
+    struct Work(u64);
+    struct Stop;
+
+    #[derive(Default)]
+    struct Summer(u64);
+
+    impl SyncActor for Summer {}
+
+    impl SyncHandler<Work> for Summer {
+        fn handle(&mut self, msg: Work, _ctx: &SyncContext<Self>) {
+            // Blocking is fine here
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            self.0 += msg.0;
+        }
+    }
+
+    impl SyncHandler<Stop> for Summer {
+        fn handle(&mut self, _msg: Stop, ctx: &SyncContext<Self>) {
+            ctx.shutdown();
+        }
+    }
+
+    #[test]
+    fn blocking_senders() {
+        let (executor, addr) = SyncExecutor::new(Summer::default());
+        let handle = executor.spawn();
+        for i in 1..=10 {
+            addr.send_blocking(Work(i));
+        }
+        addr.send_blocking(Stop);
+
+        let (executor, result) = handle.join().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(executor.actor_ref().0, 55);
+    }
+
+    #[tokio::test]
+    async fn async_senders() {
+        let (executor, addr) = SyncExecutor::new(Summer::default());
+        let handle = executor.spawn();
+        addr.send(Work(3)).await;
+        addr.try_send(Work(4));
+        drop(addr);
+
+        let (executor, result) = tokio::task::spawn_blocking(move || handle.join().unwrap())
+            .await
+            .unwrap();
+        assert!(result.is_err());
+        assert_eq!(executor.actor_ref().0, 7);
+    }
+
+    #[test]
+    fn shutdown_handle_wakes_idle_executor() {
+        let (executor, _addr) = SyncExecutor::new(Summer::default());
+        let shutdown = executor.shutdown_handle();
+        let handle = executor.spawn();
+        shutdown.shutdown().unwrap();
+
+        assert!(handle.join().unwrap().1.is_ok());
+    }
+
+    #[test]
+    fn shutdown_before_running_is_honored() {
+        let (mut executor, _addr) = SyncExecutor::new(Summer::default());
+        executor.shutdown_handle().shutdown().unwrap();
+        assert!(executor.run().is_ok());
+
+        // The executor can be run again, without the earlier shutdown carrying over
+        let addr = executor.context.address().upgrade().unwrap();
+        addr.send_blocking(Work(2));
+        addr.send_blocking(Stop);
+        assert!(executor.run().is_ok());
+        assert_eq!(executor.actor_ref().0, 2);
+    }
+}
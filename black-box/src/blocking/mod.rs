@@ -0,0 +1,162 @@
+mod executor;
+
+use async_channel::{Sender, WeakSender};
+
+use crate::{
+    actors::next_address_id,
+    dead_letter::DeadLetterReason,
+    message::{Message, SyncEnvelope},
+    ActorId,
+};
+
+pub use executor::{SyncContext, SyncExecutor};
+
+/// The [`Actor`](crate::Actor) counterpart for actors run on a [`SyncExecutor`]
+pub trait SyncActor: Sized {
+    fn starting(&mut self, _ctx: &SyncContext<Self>) {}
+
+    fn stopping(&mut self, _ctx: &SyncContext<Self>) {}
+}
+
+/// The [`Handler`](crate::Handler) counterpart for actors run on a [`SyncExecutor`]
+///
+/// Handlers are plain functions, and are free to block the thread they run on.
+pub trait SyncHandler<M>
+where
+    Self: SyncActor,
+    M: Message,
+{
+    /// Synchronously act on the message, with mutable access to self
+    fn handle(&mut self, msg: M, ctx: &SyncContext<Self>);
+}
+
+/// A cloneable address which can be used to send messages to the associated [`SyncActor`]
+///
+/// The address is `Send` and `Sync` regardless of the actor, so it can be handed to other threads.
+#[derive(Debug)]
+pub struct SyncAddress<A> {
+    id: ActorId,
+    sender: Sender<SyncEnvelope<A>>,
+}
+
+impl<A> PartialEq for SyncAddress<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<A> Clone for SyncAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<A> SyncAddress<A> {
+    pub(crate) fn new(sender: Sender<SyncEnvelope<A>>) -> Self {
+        Self {
+            id: ActorId(next_address_id()),
+            sender,
+        }
+    }
+
+    /// The id of the actor this address sends to
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    pub fn downgrade(&self) -> WeakSyncAddress<A> {
+        WeakSyncAddress {
+            id: self.id,
+            sender: self.sender.downgrade(),
+        }
+    }
+}
+
+impl<A> SyncAddress<A>
+where
+    A: 'static + SyncActor,
+{
+    /// Send the given message to the actor's receiver.
+    ///
+    /// If the receiver is currently full, it will await capacity to enqueue the message. If the
+    /// actor has stopped the message is reported to the global
+    /// [`DeadLetters`](crate::DeadLetters) sink.
+    pub async fn send<M>(&self, message: M)
+    where
+        A: SyncHandler<M>,
+        M: Message,
+    {
+        let env = SyncEnvelope::pack(message);
+
+        if let Err(err) = self.sender.send(env).await {
+            err.0.dead_letter(self.id, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the given message to the actor's receiver, blocking the current thread until there is
+    /// capacity to enqueue it
+    ///
+    /// This must not be called from within an async context.
+    pub fn send_blocking<M>(&self, message: M)
+    where
+        A: SyncHandler<M>,
+        M: Message,
+    {
+        let env = SyncEnvelope::pack(message);
+
+        if let Err(err) = self.sender.send_blocking(env) {
+            err.0.dead_letter(self.id, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the given message only if there is capacity to enqueue it immediately
+    ///
+    /// A message which cannot be enqueued is reported to the global
+    /// [`DeadLetters`](crate::DeadLetters) sink.
+    pub fn try_send<M>(&self, message: M)
+    where
+        A: SyncHandler<M>,
+        M: Message,
+    {
+        let env = SyncEnvelope::pack(message);
+
+        match self.sender.try_send(env) {
+            Ok(()) => (),
+            Err(async_channel::TrySendError::Full(env)) => {
+                env.dead_letter(self.id, DeadLetterReason::MailboxFull)
+            }
+            Err(async_channel::TrySendError::Closed(env)) => {
+                env.dead_letter(self.id, DeadLetterReason::MailboxClosed)
+            }
+        }
+    }
+}
+
+/// A weak reference to a [`SyncAddress`], which does not keep the actor alive
+#[derive(Debug)]
+pub struct WeakSyncAddress<A> {
+    id: ActorId,
+    sender: WeakSender<SyncEnvelope<A>>,
+}
+
+impl<A> Clone for WeakSyncAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<A> WeakSyncAddress<A> {
+    pub fn upgrade(&self) -> Option<SyncAddress<A>> {
+        let sender = self.sender.upgrade()?;
+        Some(SyncAddress {
+            id: self.id,
+            sender,
+        })
+    }
+}
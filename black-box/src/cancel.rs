@@ -0,0 +1,77 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use event_listener::Event;
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    event: Event,
+}
+
+/// A cooperative signal that the actor has been asked to shut down
+///
+/// Retrieved from [`Context::cancellation_token`](crate::Context::cancellation_token), the token is
+/// cheaply cloneable and can be handed off to tasks spawned by a handler so they can bail out once
+/// the actor is shutting down.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<Inner>);
+
+impl CancellationToken {
+    /// Whether shutdown has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Resolves once shutdown has been requested, immediately if it already has been
+    pub async fn cancelled(&self) {
+        loop {
+            if self.is_cancelled() {
+                return;
+            }
+
+            let listener = self.0.event.listen();
+
+            // Shutdown may have been requested between the check and registering the listener
+            if self.is_cancelled() {
+                return;
+            }
+
+            listener.await;
+        }
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+        self.0.event.notify(usize::MAX);
+    }
+
+    pub(crate) fn reset(&self) {
+        self.0.cancelled.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_wakes_all_waiters() {
+        let token = CancellationToken::default();
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let token = token.clone();
+                tokio::spawn(async move { token.cancelled().await })
+            })
+            .collect();
+
+        tokio::task::yield_now().await;
+        token.cancel();
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert!(token.is_cancelled());
+    }
+}
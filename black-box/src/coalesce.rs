@@ -0,0 +1,309 @@
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use crate::message::{Contents, Envelope};
+
+/// The pending content for a coalescing type
+#[derive(Default)]
+struct Slot {
+    /// The message along with its origin and headers, all taken from the latest send
+    content: Option<Contents>,
+    /// Bumped by each offer which has to enqueue a marker, so a stale [`Reservation`] can tell it
+    /// was taken over
+    generation: u64,
+    /// Markers in the mailbox which will claim the content
+    markers: usize,
+}
+
+/// The latest pending message for each coalescing message type, shared by a mailbox's addresses
+/// and its executor
+///
+/// Only a send which finds no marker already in the mailbox enqueues one, an envelope which is
+/// left empty while its content waits in the slot. Further sends replace the content until the
+/// executor claims it, so the mailbox holds at most one envelope per coalescing type, except while
+/// sends race to enqueue a marker. Requests are never coalesced, as each expects its own response.
+#[derive(Default)]
+pub(crate) struct Coalescing {
+    /// Lets sends skip the lock entirely until a type is registered
+    enabled: AtomicBool,
+    slots: Mutex<HashMap<TypeId, Slot>>,
+}
+
+impl std::fmt::Debug for Coalescing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coalescing")
+            .field("enabled", &self.enabled.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Coalescing {
+    pub(crate) fn register(&self, id: TypeId) {
+        self.slots.lock().unwrap().entry(id).or_default();
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// The coalescing message types
+    pub(crate) fn registered(&self) -> Vec<TypeId> {
+        self.slots.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Returns the envelope to enqueue, or `None` if it replaced a message which is still pending
+    ///
+    /// The returned [`Reservation`] must be committed once the envelope has been enqueued,
+    /// otherwise the content is withdrawn from the slot when it is dropped. If a later offer for
+    /// the same type takes over before then, the content is left for it instead.
+    pub(crate) fn offer<A>(&self, env: Envelope<A>) -> Option<(Envelope<A>, Reservation<'_>)> {
+        let id = env.type_id();
+        if !self.enabled.load(Ordering::Acquire) || env.is_scheduled() || env.is_request() {
+            return Some((env, Reservation::none()));
+        }
+
+        let mut slots = self.slots.lock().unwrap();
+        let Some(slot) = slots.get_mut(&id) else {
+            return Some((env, Reservation::none()));
+        };
+
+        let (marker, content) = env.into_marker();
+        slot.content = Some(content);
+        if slot.markers > 0 {
+            return None;
+        }
+
+        slot.generation += 1;
+        Some((marker, Reservation::new(self, id, slot.generation)))
+    }
+
+    /// Fill an envelope taken from the mailbox with the latest content for its type
+    ///
+    /// Returns `None` if there is nothing left to handle.
+    pub(crate) fn claim<A>(&self, env: Envelope<A>) -> Option<Envelope<A>> {
+        if !env.is_coalesced() {
+            return Some(env);
+        }
+
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.get_mut(&env.type_id())?;
+        slot.markers = slot.markers.saturating_sub(1);
+        let content = slot.content.take()?;
+        Some(env.refill(content))
+    }
+
+    fn commit(&self, id: TypeId, generation: u64, enqueued_at: Instant) {
+        if let Some(slot) = self.slots.lock().unwrap().get_mut(&id) {
+            slot.markers += 1;
+            // Content from a later offer was stamped as it replaced this one
+            if slot.generation == generation {
+                if let Some(content) = &mut slot.content {
+                    content.stamp(enqueued_at);
+                }
+            }
+        }
+    }
+
+    /// Takes the content back, unless a later offer took over or a queued marker will claim it
+    fn withdraw(&self, id: TypeId, generation: u64) -> Option<Contents> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.get_mut(&id)?;
+        if slot.generation != generation || slot.markers > 0 {
+            return None;
+        }
+        slot.content.take()
+    }
+}
+
+/// Withdraws the content offered to a slot unless committed
+pub(crate) struct Reservation<'a> {
+    slot: Option<(&'a Coalescing, TypeId, u64)>,
+}
+
+impl<'a> Reservation<'a> {
+    fn new(coalescing: &'a Coalescing, id: TypeId, generation: u64) -> Self {
+        Self {
+            slot: Some((coalescing, id, generation)),
+        }
+    }
+
+    fn none() -> Self {
+        Self { slot: None }
+    }
+
+    /// The envelope was enqueued at `enqueued_at`, keep the content in the slot for the executor
+    /// to claim
+    pub(crate) fn commit(mut self, enqueued_at: Instant) {
+        if let Some((coalescing, id, generation)) = self.slot.take() {
+            coalescing.commit(id, generation, enqueued_at);
+        }
+    }
+
+    /// The envelope could not be enqueued, move the content back into it
+    ///
+    /// Returns `None` if the content is no longer this envelope's to report, as it was superseded
+    /// by a later send or will be claimed through another marker.
+    pub(crate) fn restore<A>(mut self, env: Envelope<A>) -> Option<Envelope<A>> {
+        match self.slot.take() {
+            Some((coalescing, id, generation)) => coalescing
+                .withdraw(id, generation)
+                .map(|content| env.refill(content)),
+            None => Some(env),
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some((coalescing, id, generation)) = self.slot {
+            coalescing.withdraw(id, generation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Actor, Context, Executor, Handler, Headers, Request, RequestHandler};
+
+    struct Position(usize);
+    struct Ping;
+
+    #[derive(Default)]
+    struct Tracker {
+        log: Vec<String>,
+    }
+
+    impl Actor for Tracker {}
+
+    impl Handler<Position> for Tracker {
+        async fn handle(&mut self, msg: Position, _ctx: &Context<Self>) {
+            self.log.push(format!("position {}", msg.0));
+        }
+    }
+
+    impl Request for Position {
+        type Response = usize;
+    }
+
+    impl RequestHandler<Position> for Tracker {
+        async fn respond(&mut self, msg: Position, _ctx: &Context<Self>) -> usize {
+            msg.0
+        }
+    }
+
+    impl Handler<Ping> for Tracker {
+        async fn handle(&mut self, _msg: Ping, _ctx: &Context<Self>) {
+            self.log.push("ping".into());
+        }
+    }
+
+    #[tokio::test]
+    async fn latest_pending_message_wins() {
+        let (mut executor, addr) = Executor::new_with_capacity(Tracker::default(), 3);
+        executor.set_coalescing::<Position>();
+        addr.send(Ping).await;
+        for i in 0..10 {
+            addr.send(Position(i)).await;
+        }
+        addr.send(Ping).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().log, ["ping", "position 9", "ping"]);
+    }
+
+    #[tokio::test]
+    async fn replacement_keeps_its_own_headers() {
+        struct Traced(Vec<String>);
+        impl Actor for Traced {}
+        impl Handler<Position> for Traced {
+            async fn handle(&mut self, msg: Position, ctx: &Context<Self>) {
+                let id = ctx.headers().correlation_id().unwrap_or("-");
+                self.0.push(format!("position {} {id}", msg.0));
+            }
+        }
+
+        let (mut executor, addr) = Executor::new(Traced(Vec::new()));
+        executor.set_coalescing::<Position>();
+        let mut first = Headers::new();
+        first.set_correlation_id("first");
+        addr.send_with_headers(Position(1), first).await;
+        // Sent without headers, so none of the first send's may be left on the envelope
+        addr.send(Position(2)).await;
+        let mut third = Headers::new();
+        third.set_correlation_id("third");
+        addr.send_with_headers(Position(3), third).await;
+        addr.send(Position(4)).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().0, ["position 4 -"]);
+    }
+
+    #[tokio::test]
+    async fn failed_send_is_withdrawn() {
+        let (mut executor, addr) = Executor::new_with_capacity(Tracker::default(), 1);
+        executor.set_coalescing::<Position>();
+        addr.try_send(Ping);
+        // The mailbox is full, so this is dropped rather than left pending
+        addr.try_send(Position(1));
+
+        let handle = tokio::spawn(async move {
+            let _ = executor.run().await;
+            executor
+        });
+        addr.send(Position(2)).await;
+        drop(addr);
+
+        let executor = handle.await.unwrap();
+        assert_eq!(executor.actor_ref().log, ["ping", "position 2"]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_send_leaves_later_replacement() {
+        let (mut executor, addr) = Executor::new_with_capacity(Tracker::default(), 1);
+        executor.set_coalescing::<Position>();
+        addr.try_send(Ping);
+        // Both wait for capacity, the second taking over the slot from the first
+        let first = tokio::spawn({
+            let addr = addr.clone();
+            async move { addr.send(Position(1)).await }
+        });
+        tokio::task::yield_now().await;
+        let second = tokio::spawn({
+            let addr = addr.clone();
+            async move { addr.send(Position(2)).await }
+        });
+        tokio::task::yield_now().await;
+        first.abort();
+        assert!(first.await.is_err());
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        second.await.unwrap();
+        assert_eq!(executor.actor_ref().log, ["ping", "position 2"]);
+    }
+
+    #[tokio::test]
+    async fn requests_are_not_coalesced() {
+        let (mut executor, addr) = Executor::new(Tracker::default());
+        executor.set_coalescing::<Position>();
+        let asks: Vec<_> = (1..=2)
+            .map(|i| {
+                let addr = addr.clone();
+                tokio::spawn(async move { addr.ask(Position(i)).await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        drop(addr);
+
+        tokio::spawn(async move { executor.run().await });
+        for (i, ask) in (1..=2).zip(asks) {
+            assert_eq!(ask.await.unwrap(), Ok(i));
+        }
+    }
+}
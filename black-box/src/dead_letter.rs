@@ -0,0 +1,264 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    sync::{Arc, RwLock},
+};
+
+use crate::{Actor, ActorId, Address, Handler};
+
+/// Why a [`DeadLetter`] could not be delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DeadLetterReason {
+    /// The message was sent to an actor whose mailbox is closed
+    MailboxClosed,
+    /// The message was dropped by a `try_send` as the mailbox was full
+    MailboxFull,
+    /// The message was still queued when the executor was dropped
+    Shutdown,
+    /// The handler panicked while handling the message
+    HandlerPanicked,
+    /// The actor does not currently accept the message, see
+    /// [`Unhandled::DeadLetter`](crate::Unhandled::DeadLetter)
+    Unhandled,
+    /// A [`Middleware`](crate::Middleware) dropped the message without calling
+    /// [`Next::run`](crate::Next::run)
+    Rejected,
+}
+
+impl std::fmt::Display for DeadLetterReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeadLetterReason::MailboxClosed => f.write_str("mailbox closed"),
+            DeadLetterReason::MailboxFull => f.write_str("mailbox full"),
+            DeadLetterReason::Shutdown => f.write_str("queued at shutdown"),
+            DeadLetterReason::HandlerPanicked => f.write_str("handler panicked"),
+            DeadLetterReason::Unhandled => f.write_str("unhandled"),
+            DeadLetterReason::Rejected => f.write_str("rejected by middleware"),
+        }
+    }
+}
+
+/// A message which could not be delivered to its actor
+#[derive(Debug)]
+pub struct DeadLetter {
+    type_name: &'static str,
+    target: ActorId,
+    reason: DeadLetterReason,
+    message: Option<Box<dyn Any + Send>>,
+}
+
+impl DeadLetter {
+    pub(crate) fn new(
+        type_name: &'static str,
+        target: ActorId,
+        reason: DeadLetterReason,
+        message: Option<Box<dyn Any + Send>>,
+    ) -> Self {
+        Self {
+            type_name,
+            target,
+            reason,
+            message,
+        }
+    }
+
+    /// The type name of the undelivered message
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The actor the message was addressed to
+    pub fn target(&self) -> ActorId {
+        self.target
+    }
+
+    pub fn reason(&self) -> DeadLetterReason {
+        self.reason
+    }
+
+    /// Recover the undelivered message, if it is of type `M`
+    ///
+    /// The message is unavailable if it was consumed, as it is when a handler panics.
+    pub fn downcast<M: 'static>(self) -> Result<M, Self> {
+        match self.message {
+            Some(message) if message.is::<M>() => Ok(*message.downcast().unwrap()),
+            message => Err(Self { message, ..self }),
+        }
+    }
+}
+
+static GLOBAL: RwLock<Option<DeadLetters>> = RwLock::new(None);
+
+thread_local! {
+    /// Set while a sink is running, so a sink which itself fails to deliver a message cannot
+    /// recurse indefinitely
+    static DELIVERING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A sink for [`DeadLetter`]s
+///
+/// A sink set on an [`Executor`](crate::Executor) with
+/// [`Executor::set_dead_letters`](crate::Executor::set_dead_letters) receives the dead letters
+/// for that actor, any others go to the process-wide sink set with [`DeadLetters::set_global`].
+/// Without either, undeliverable messages are dropped.
+///
+/// Dead letters produced while a sink is running are dropped.
+///
+/// # Example
+///
+/// ```
+/// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+/// # use black_box::*;
+/// let count = Arc::new(AtomicUsize::new(0));
+/// let counter = count.clone();
+/// DeadLetters::new(move |letter: DeadLetter| {
+///     eprintln!("{} to {} dropped: {}", letter.type_name(), letter.target(), letter.reason());
+///     counter.fetch_add(1, Ordering::Relaxed);
+/// })
+/// .set_global();
+/// ```
+#[derive(Clone)]
+pub struct DeadLetters(Arc<dyn Fn(DeadLetter) + Send + Sync>);
+
+impl std::fmt::Debug for DeadLetters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeadLetters").finish_non_exhaustive()
+    }
+}
+
+impl DeadLetters {
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: Fn(DeadLetter) + Send + Sync + 'static,
+    {
+        Self(Arc::new(sink))
+    }
+
+    /// A sink which forwards each dead letter to an actor
+    ///
+    /// Dead letters are sent with [`Address::try_send`], so they are dropped rather than waited
+    /// on if the actor's mailbox is full.
+    pub fn forward_to<B>(address: Address<B>) -> Self
+    where
+        B: 'static + Actor + Handler<DeadLetter> + Send,
+    {
+        Self::new(move |letter| address.try_send(letter))
+    }
+
+    /// Use this sink for every actor without its own sink
+    pub fn set_global(self) {
+        *GLOBAL.write().unwrap() = Some(self);
+    }
+
+    /// Remove the process-wide sink
+    pub fn clear_global() {
+        *GLOBAL.write().unwrap() = None;
+    }
+
+    /// Hand the letter to `sink`, falling back to the global sink
+    pub(crate) fn deliver(sink: Option<Self>, letter: DeadLetter) {
+        if DELIVERING.with(Cell::get) {
+            return;
+        }
+
+        let Some(sink) = sink.or_else(|| GLOBAL.read().unwrap().clone()) else {
+            return;
+        };
+
+        DELIVERING.with(|delivering| delivering.set(true));
+        // Reset even if the sink panics, so the thread can still report dead letters afterwards
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                DELIVERING.with(|delivering| delivering.set(false));
+            }
+        }
+        let _reset = Reset;
+        (sink.0)(letter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{Context, Executor};
+
+    use super::*;
+
+    struct Ping(usize);
+    struct Panic;
+
+    struct Pong;
+    impl Actor for Pong {}
+    impl Handler<Ping> for Pong {
+        async fn handle(&mut self, _msg: Ping, _ctx: &Context<Self>) {}
+    }
+    impl Handler<Panic> for Pong {
+        async fn handle(&mut self, _msg: Panic, _ctx: &Context<Self>) {
+            panic!("handler panicked");
+        }
+    }
+
+    type Letters = Arc<Mutex<Vec<DeadLetter>>>;
+
+    fn collect(executor: &mut Executor<Pong>) -> Letters {
+        let letters = Letters::default();
+        let sink = letters.clone();
+        executor.set_dead_letters(DeadLetters::new(move |letter| {
+            sink.lock().unwrap().push(letter);
+        }));
+        letters
+    }
+
+    fn reasons(letters: &Letters) -> Vec<DeadLetterReason> {
+        letters.lock().unwrap().iter().map(|l| l.reason()).collect()
+    }
+
+    #[tokio::test]
+    async fn undeliverable_messages_are_reported() {
+        let (mut executor, addr) = Executor::new_with_capacity(Pong, 1);
+        let letters = collect(&mut executor);
+
+        let delay = std::time::Duration::from_secs(60);
+        addr.send_after(Ping(1), delay).await;
+        addr.try_send(Ping(2));
+        addr.try_send(Ping(3));
+        drop(executor);
+        addr.send(Ping(4)).await;
+
+        use DeadLetterReason::*;
+        assert_eq!(
+            reasons(&letters),
+            [MailboxFull, Shutdown, Shutdown, MailboxClosed]
+        );
+        let letters = std::mem::take(&mut *letters.lock().unwrap());
+        let numbers = letters
+            .into_iter()
+            .map(|letter| {
+                assert_eq!(letter.target(), addr.id());
+                assert_eq!(letter.type_name(), std::any::type_name::<Ping>());
+                letter.downcast::<Ping>().unwrap().0
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, [3, 1, 2, 4]);
+    }
+
+    #[tokio::test]
+    async fn panicking_handlers_are_reported() {
+        let (mut executor, addr) = Executor::new(Pong);
+        let letters = collect(&mut executor);
+        addr.send(Panic).await;
+        addr.send(Ping(1)).await;
+
+        let run = tokio::spawn(async move { executor.run().await });
+        assert!(run.await.unwrap_err().is_panic());
+
+        use DeadLetterReason::*;
+        assert_eq!(reasons(&letters), [HandlerPanicked, Shutdown]);
+        let panicked = letters.lock().unwrap().remove(0);
+        assert_eq!(panicked.type_name(), std::any::type_name::<Panic>());
+        assert!(panicked.downcast::<Panic>().is_err());
+    }
+}
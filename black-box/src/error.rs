@@ -28,3 +28,62 @@ impl std::fmt::Display for AddressError {
 }
 
 impl std::error::Error for AddressError {}
+
+/// The reason an [`Address::ask`](crate::Address::ask) did not receive a response
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AskError {
+    /// No response arrived before the deadline, the actor may still be handling the request
+    Timeout,
+    /// The request could not be enqueued, as the actor's mailbox is closed
+    MailboxClosed,
+    /// The request could not be enqueued before the deadline, as the actor's mailbox was full
+    MailboxFull,
+    /// The request was dropped before it was answered, for instance because the handler timed
+    /// out or the actor was shut down
+    HandlerDropped,
+    /// A [`Middleware`](crate::Middleware) dropped the request before it reached its handler
+    Rejected,
+    /// The handler panicked while answering the request
+    ActorPanicked,
+}
+
+impl std::fmt::Display for AskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AskError::Timeout => f.write_str("Request timed out"),
+            AskError::MailboxClosed => f.write_str("Mailbox closed for actor"),
+            AskError::MailboxFull => f.write_str("Mailbox full for actor"),
+            AskError::HandlerDropped => f.write_str("Request dropped before it was answered"),
+            AskError::Rejected => f.write_str("Request rejected by middleware"),
+            AskError::ActorPanicked => f.write_str("Actor panicked answering the request"),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
+
+/// The reason a [`Context::reply`](crate::Context::reply) could not be sent
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ReplyError {
+    /// The message being handled was not sent from within another actor's handler
+    NoSender,
+    /// The sender does not accept replies of this type, see
+    /// [`Executor::accept_reply`](crate::Executor::accept_reply)
+    NotAccepted,
+    /// The sender has stopped
+    MailboxClosed,
+}
+
+impl std::fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplyError::NoSender => f.write_str("Message was not sent by an actor"),
+            ReplyError::NotAccepted => f.write_str("Sender does not accept the reply"),
+            ReplyError::MailboxClosed => f.write_str("Mailbox closed for sender"),
+        }
+    }
+}
+
+impl std::error::Error for ReplyError {}
@@ -1,21 +1,43 @@
-use std::future::Future;
+use std::{
+    any::TypeId,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_channel::{Receiver, Sender};
 
 use crate::{
-    Actor, Address, WeakAddress,
-    error::{ActorError, AddressError},
-    message::Envelope,
+    batch::{BatchHandler, Batches},
+    cancel::CancellationToken,
+    dead_letter::{DeadLetterReason, DeadLetters},
+    error::{ActorError, AddressError, AskError, ReplyError},
+    futures::catch_unwind_future,
+    headers::Headers,
+    mailbox::Mailbox,
+    message::{Envelope, Message},
+    middleware::{Chain, Middleware, Rejected, Work},
+    pending::Pending,
+    reply::{Origin, Replies},
+    schedule::Schedule,
+    scope::{Current, Scoped},
+    snapshot::{Snapshot, SnapshotStore, Snapshots},
+    stash::Stash,
+    timer::{SharedTimer, Sleep, Timer},
+    Actor, ActorId, Address, Handler, WeakAddress,
 };
 
-const DEFAULT_CAP: usize = 100;
+pub(crate) const DEFAULT_CAP: usize = 100;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum State {
+pub(crate) enum State {
     #[default]
     Continue,
     Shutdown,
     SendersClosed,
+    /// Requests a snapshot of the actor, without changing the state of the executor
+    Snapshot,
 }
 
 /// A cloneable context for the actor.
@@ -27,15 +49,100 @@ enum State {
 pub struct Context<A> {
     sender: async_channel::Sender<State>,
     address: WeakAddress<A>,
+    token: CancellationToken,
+    stash: Stash<A>,
+    /// The sender of the message currently being handled
+    origin: Option<Origin>,
+    headers: Headers,
 }
 
 impl<A> Context<A> {
     /// Triggers the end of the executor.
     ///
     /// Once triggered, no new messages will be processed and the actor will exit after resolving
-    /// [`Actor::stopping`]
+    /// [`Actor::stopping`]. Handlers currently awaiting [`Self::cancelled`] are woken.
     pub fn shutdown(&self) {
         let _ = self.sender.force_send(State::Shutdown);
+        self.token.cancel();
+    }
+
+    /// Resolves once shutdown of the actor has been requested
+    ///
+    /// Shutdown normally only takes effect between messages, long running handlers can race
+    /// their work against this future to bail out early.
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Whether shutdown of the actor has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// A cloneable token which can be handed to tasks spawned from within a handler
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Request that the executor take a snapshot of the actor once the current handler returns
+    ///
+    /// Has no effect unless a store has been registered with [`Executor::set_snapshot_store`].
+    pub fn snapshot(&self) {
+        let _ = self.sender.force_send(State::Snapshot);
+    }
+
+    /// Defer the message until [`Self::unstash_all`] is called
+    ///
+    /// This is intended to be called by a handler with the message it was given, when the actor is
+    /// not yet ready to act on it, for instance while it awaits initialization. Stashed messages
+    /// are held by the executor and do not take up capacity in the mailbox.
+    pub fn stash<M>(&self, message: M)
+    where
+        A: 'static + Handler<M> + Send,
+        M: Message,
+    {
+        // Replayed as if sent by the original sender
+        let mut env = Envelope::pack(message);
+        env.set_origin(self.origin.clone());
+        env.set_headers(self.headers.clone());
+        self.stash.push(env);
+    }
+
+    /// Replay every stashed message, in the order they were stashed
+    ///
+    /// Replayed messages are handled before anything else waiting in the mailbox, starting once
+    /// the current handler returns.
+    pub fn unstash_all(&self) {
+        self.stash.unstash_all();
+    }
+
+    /// The number of messages currently stashed
+    pub fn stashed(&self) -> usize {
+        self.stash.len()
+    }
+
+    /// The actor which sent the message currently being handled
+    ///
+    /// This is `None` unless the message was sent from within another actor's handler, including
+    /// this actor's own. Messages handled as a batch have no sender.
+    pub fn sender(&self) -> Option<ActorId> {
+        self.origin.as_ref().map(Origin::id)
+    }
+
+    /// The headers of the message currently being handled
+    ///
+    /// Messages handled as a batch share the headers of the first message in the batch.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Send `message` to the actor which sent the message currently being handled
+    ///
+    /// The sender must accept replies of type `R`, see [`Executor::accept_reply`]. Waits for
+    /// capacity in the sender's mailbox as with [`Address::send`].
+    pub async fn reply<R: Message>(&self, message: R) -> Result<(), ReplyError> {
+        let origin = self.origin.as_ref().ok_or(ReplyError::NoSender)?;
+        origin.reply(message).await
     }
 
     /// Retrieve the address for the executor's actor
@@ -66,21 +173,91 @@ impl<A> Context<A> {
 /// ```
 #[derive(Debug)]
 pub struct Executor<A> {
-    actor: A,
+    /// Only taken by [`Self::into_parts`]
+    actor: Option<A>,
     context: Context<A>,
     state: State,
     from_context: Receiver<State>,
     receiver: Receiver<Envelope<A>>,
+    /// Envelopes taken from the mailbox ahead of their turn, handled before the mailbox
+    backlog: VecDeque<Envelope<A>>,
+    mailbox: Arc<Mailbox>,
+    /// Attached to messages sent from the actor's handlers
+    origin: Origin,
+    /// Created by the first call to [`Self::accept_reply`]
+    replies: Option<Arc<Replies<A>>>,
+    timer: SharedTimer,
+    /// Kept across continuations, so the sleep is only recreated when the wake time changes
+    wake: Option<Wake>,
+    timeouts: Timeouts,
+    schedule: Schedule<A>,
+    forceful_shutdown: bool,
+    idle_timeout: Option<Duration>,
+    /// When the last message was received, for the idle timeout
+    last_active: Instant,
+    snapshots: Option<Snapshots<A>>,
+    middleware: Chain<A>,
+    batches: Batches<A>,
+}
+
+/// The sleep until the executor next needs to act without being sent anything
+struct Wake {
+    at: Instant,
+    sleep: Sleep,
+}
+
+impl std::fmt::Debug for Wake {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wake")
+            .field("at", &self.at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The configuration of an executor, handed from [`Executor::into_parts`] to
+/// [`Executor::from_parts`] along with the pending messages
+pub(crate) struct Settings<A> {
+    capacity: usize,
+    timer: SharedTimer,
+    timeouts: Timeouts,
+    forceful_shutdown: bool,
+    idle_timeout: Option<Duration>,
+    snapshots: Option<Snapshots<A>>,
+    middleware: Chain<A>,
+    batches: Batches<A>,
+    coalescing: Vec<TypeId>,
+    dead_letters: Option<DeadLetters>,
+    replies: Option<Arc<Replies<A>>>,
+}
+
+/// Handler deadlines, with optional per-message overrides
+#[derive(Debug, Default)]
+struct Timeouts {
+    default: Option<Duration>,
+    overrides: HashMap<TypeId, Option<Duration>>,
+}
+
+impl Timeouts {
+    fn get(&self, id: TypeId) -> Option<Duration> {
+        self.overrides.get(&id).copied().unwrap_or(self.default)
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct ShutdownHandle(Sender<State>);
+pub struct ShutdownHandle {
+    sender: Sender<State>,
+    token: CancellationToken,
+}
 
 impl ShutdownHandle {
+    pub(crate) fn new(sender: Sender<State>, token: CancellationToken) -> Self {
+        Self { sender, token }
+    }
+
     pub fn shutdown(&self) -> Result<(), ActorError> {
-        self.0
+        self.sender
             .force_send(State::Shutdown)
-            .map(|_| ())
+            .map(|_| self.token.cancel())
             .map_err(|_| ActorError::Shutdown)
     }
 }
@@ -91,35 +268,293 @@ impl<A> Executor<A> {
     }
 
     pub fn new_with_capacity(actor: A, cap: usize) -> (Self, Address<A>) {
-        let (sender, receiver) = async_channel::bounded(cap);
-        let address = Address::new(sender);
+        // The capacity is enforced by the mailbox, see `Mailbox::acquire`
+        let (sender, receiver) = async_channel::unbounded();
+        let mailbox = Arc::new(Mailbox::new(Some(cap)));
+        let address = Address::new(sender, mailbox.clone());
         let (state_tx, state_rx) = async_channel::unbounded();
         let me = Self {
-            actor,
+            actor: Some(actor),
             receiver,
+            backlog: VecDeque::new(),
+            origin: Origin::new(mailbox.id()),
+            replies: None,
+            mailbox,
             context: Context {
                 sender: state_tx,
                 address: address.downgrade(),
+                token: Default::default(),
+                stash: Default::default(),
+                origin: None,
+                headers: Headers::default(),
             },
             from_context: state_rx,
             state: Default::default(),
+            timer: Default::default(),
+            wake: None,
+            timeouts: Default::default(),
+            schedule: Default::default(),
+            forceful_shutdown: false,
+            idle_timeout: None,
+            last_active: Instant::now(),
+            snapshots: None,
+            middleware: Default::default(),
+            batches: Default::default(),
         };
 
         (me, address)
     }
 
+    /// Construct an executor for an actor recovered with [`Self::into_parts`]
+    ///
+    /// The pending messages are handled before any sent to the new address, and those which were
+    /// stashed remain stashed until the actor calls [`Context::unstash_all`]. The new executor
+    /// keeps the configuration of the one it was split from, including its capacity, timer,
+    /// middleware, batching, coalescing, timeouts, snapshots, dead letter sink and accepted
+    /// replies. Only its id and addresses are new.
+    pub fn from_parts(actor: A, pending: Pending<A>) -> (Self, Address<A>)
+    where
+        A: 'static,
+    {
+        let Pending {
+            queued,
+            stashed,
+            settings,
+        } = pending;
+        let capacity = settings
+            .as_ref()
+            .map_or(DEFAULT_CAP, |settings| settings.capacity);
+        let (mut me, address) = Self::new_with_capacity(actor, capacity);
+        if let Some(settings) = settings {
+            me.apply_settings(settings);
+        }
+        me.backlog = queued;
+        me.context.stash.extend(stashed);
+        (me, address)
+    }
+
+    /// Stop the executor, returning the actor along with every message it has yet to handle
+    ///
+    /// This includes messages waiting in the mailbox, stashed, or held back until a deadline.
+    /// These can be handed to another executor with [`Self::from_parts`] or [`Pending::resend`].
+    /// The mailbox is closed, so the actor's existing addresses can no longer send to it.
+    pub fn into_parts(mut self) -> (A, Pending<A>) {
+        let mut pending = self.take_pending();
+        pending.settings = Some(self.take_settings());
+        (self.actor.take().unwrap(), pending)
+    }
+
+    fn take_settings(&mut self) -> Settings<A> {
+        Settings {
+            capacity: self.mailbox.capacity().unwrap_or(DEFAULT_CAP),
+            timer: self.timer.clone(),
+            timeouts: std::mem::take(&mut self.timeouts),
+            forceful_shutdown: self.forceful_shutdown,
+            idle_timeout: self.idle_timeout,
+            snapshots: self.snapshots.take(),
+            middleware: std::mem::take(&mut self.middleware),
+            batches: std::mem::take(&mut self.batches),
+            coalescing: self.mailbox.coalescing.registered(),
+            dead_letters: self.mailbox.dead_letters(),
+            replies: self.replies.take(),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: Settings<A>)
+    where
+        A: 'static,
+    {
+        self.timer = settings.timer;
+        self.mailbox.set_timer(self.timer.clone());
+        self.timeouts = settings.timeouts;
+        self.forceful_shutdown = settings.forceful_shutdown;
+        self.idle_timeout = settings.idle_timeout;
+        self.snapshots = settings.snapshots;
+        self.middleware = settings.middleware;
+        self.batches = settings.batches;
+        for id in settings.coalescing {
+            self.mailbox.coalescing.register(id);
+        }
+        self.mailbox.set_dead_letters(settings.dead_letters);
+        if let Some(replies) = settings.replies {
+            let replies = Arc::new(replies.rebind(self.context.address.clone()));
+            self.origin.set_replies(replies.clone());
+            self.replies = Some(replies);
+        }
+    }
+
+    /// Moves everything from the mailbox to the back of the backlog
+    fn fill_backlog(&mut self) {
+        self.context.stash.replay_into(&mut self.backlog);
+        while let Ok(env) = self.receiver.try_recv() {
+            self.backlog.push_back(env);
+        }
+    }
+
+    /// Close the mailbox and remove everything waiting to be handled
+    fn take_pending(&mut self) -> Pending<A> {
+        self.receiver.close();
+        self.mailbox.close();
+        self.fill_backlog();
+        let mut queued = std::mem::take(&mut self.backlog);
+        queued.extend(self.schedule.drain());
+        // Coalesced envelopes are filled while the mailbox can still provide their content
+        let queued = queued
+            .into_iter()
+            .filter_map(|mut env| {
+                self.mailbox.release(&mut env);
+                self.mailbox.coalescing.claim(env)
+            })
+            .collect();
+
+        Pending {
+            queued,
+            stashed: self.context.stash.take(),
+            settings: None,
+        }
+    }
+
+    /// Replace the [`Timer`] used by the executor, by default this is a
+    /// [`ThreadTimer`](crate::ThreadTimer)
+    ///
+    /// The timer also measures [`Address::ask_timeout`](crate::Address::ask_timeout) for
+    /// requests to this actor.
+    pub fn set_timer<T: Timer>(&mut self, timer: T) {
+        self.timer = SharedTimer::new(timer);
+        self.mailbox.set_timer(self.timer.clone());
+        self.wake = None;
+    }
+
+    /// Set the deadline applied to every [`Handler::handle`](crate::Handler::handle) call
+    ///
+    /// A handler which runs past its deadline is dropped, and [`Actor::on_timeout`] is invoked
+    /// with the type name of the message. `None` disables the deadline, which is the default.
+    pub fn set_handler_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.default = timeout;
+    }
+
+    /// Override the handler deadline for messages of type `M`
+    ///
+    /// Takes precedence over [`Self::set_handler_timeout`], `None` disables the deadline for `M`
+    /// entirely.
+    pub fn set_handler_timeout_for<M: Message>(&mut self, timeout: Option<Duration>) {
+        self.timeouts.overrides.insert(TypeId::of::<M>(), timeout);
+    }
+
+    /// When enabled, a handler which is still running once shutdown is requested is dropped
+    /// rather than awaited to completion
+    ///
+    /// This is disabled by default, in which case handlers can cooperatively bail out via
+    /// [`Context::cancelled`].
+    pub fn set_forceful_shutdown(&mut self, forceful: bool) {
+        self.forceful_shutdown = forceful;
+    }
+
+    /// Stop the actor once it has gone `timeout` without receiving a message
+    ///
+    /// An idle executor closes its mailbox, handles anything which raced in before the close, and
+    /// then exits as though every address had been dropped. Pending scheduled messages keep the
+    /// actor from going idle. `None` disables the timeout, which is the default.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Store snapshots of the actor in `store`, taken every `interval` and whenever requested with
+    /// [`Context::snapshot`]
+    ///
+    /// Periodic snapshots are taken between messages, the first once `interval` has elapsed after
+    /// the executor starts. `None` only takes snapshots on request.
+    pub fn set_snapshot_store<S>(&mut self, store: S, interval: Option<Duration>)
+    where
+        A: Snapshot,
+        S: SnapshotStore,
+    {
+        self.snapshots = Some(Snapshots::new(store, interval));
+    }
+
+    /// Wrap the dispatch of every message in the given [`Middleware`]
+    ///
+    /// Middleware is invoked in the order it was added, the first added being the outermost.
+    pub fn add_middleware<M: Middleware<A> + 'static>(&mut self, middleware: M) {
+        self.middleware.push(middleware);
+    }
+
+    /// Handle messages of type `M` in batches of up to `limit` with [`BatchHandler::handle_batch`]
+    ///
+    /// Middleware sees each batch once as a whole, and the handler deadline for `M` applies to the
+    /// batch as a whole too. Requests sent with [`Address::ask`] are never batched, as each
+    /// expects its own response.
+    pub fn set_batch_limit<M>(&mut self, limit: usize)
+    where
+        A: BatchHandler<M> + Send,
+        M: Message,
+    {
+        self.batches.insert::<M>(limit);
+    }
+
+    /// Only keep the latest pending message of type `M`
+    ///
+    /// Sending an `M` while an older one is still waiting in the mailbox replaces the older one,
+    /// rather than queueing behind it. The replacement keeps the older message's place in the
+    /// queue, and does not take up any further capacity, but is handled with its own headers and
+    /// sender. Messages sent with
+    /// [`Address::send_after`] or [`Address::send_at`] are never coalesced, nor are requests sent
+    /// with [`Address::ask`], as each expects its own response.
+    pub fn set_coalescing<M>(&mut self)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        self.mailbox.coalescing.register(TypeId::of::<M>());
+    }
+
+    /// The id of the actor, shared with each of its addresses
+    pub fn id(&self) -> ActorId {
+        self.mailbox.id()
+    }
+
+    /// Report this actor's undeliverable messages to `sink`, rather than the global sink set with
+    /// [`DeadLetters::set_global`]
+    pub fn set_dead_letters(&mut self, sink: DeadLetters) {
+        self.mailbox.set_dead_letters(Some(sink));
+    }
+
+    /// Accept replies of type `R` sent with [`Context::reply`] by actors this actor sends to
+    ///
+    /// Replies are delivered to the actor's mailbox like any other message.
+    pub fn accept_reply<R>(&mut self)
+    where
+        A: 'static + Handler<R> + Send,
+        R: Message,
+    {
+        let replies = self.replies.get_or_insert_with(|| {
+            let replies = Arc::new(Replies::new(self.context.address.clone()));
+            self.origin.set_replies(replies.clone());
+            replies
+        });
+        replies.accept::<R>();
+    }
+
     /// Construct a new shutdown handle to be able to remotely shutdown the actor
     pub fn shutdown_handle(&self) -> ShutdownHandle {
-        let sender = self.context.sender.clone();
-        ShutdownHandle(sender)
+        ShutdownHandle::new(self.context.sender.clone(), self.context.token.clone())
     }
 }
 
 enum Race<A> {
     State(State),
+    Due,
+    Idle,
     Envelope(Envelope<A>),
 }
 
+enum Outcome {
+    Handled,
+    TimedOut,
+    Cancelled,
+    Panicked(Box<dyn std::any::Any + Send>),
+}
+
 impl<A> Executor<A>
 where
     A: Actor,
@@ -128,22 +563,22 @@ where
     /// if all addresses to the actor have been dropped
     ///
     /// This function should be likely be handed off to the spawn function of your async runtime
-    /// of choice.
+    /// of choice. A shutdown requested before the executor runs is honored once it starts.
     pub async fn run(&mut self) -> Result<(), AddressError> {
-        self.reset_state();
-        self.actor.starting(&self.context).await;
+        self.start().await;
 
         // TODO: In the future we will likely add more states, this is fine for now
         #[allow(clippy::while_let_loop)]
         let result = loop {
             match self.state {
-                State::Continue => self.continuation().await,
+                State::Continue | State::Snapshot => self.continuation().await,
                 State::Shutdown => break Ok(()),
                 State::SendersClosed => break Err(AddressError::Closed),
             }
         };
 
-        self.actor.stopping(&self.context).await;
+        self.stop().await;
+        self.reset_state();
 
         result
     }
@@ -159,7 +594,6 @@ where
     where
         F: Future<Output = ()>,
     {
-        self.reset_state();
         let fut1 = async { self.run().await.map(|_| false) };
         let fut2 = async {
             fut.await;
@@ -169,44 +603,569 @@ where
         crate::futures::race_biased(fut1, fut2).await
     }
 
-    /// Resets the actor's state
+    /// Discards any state changes left over from a run, so the executor can be run again
     fn reset_state(&mut self) {
         while self.from_context.try_recv().is_ok() {}
+        self.context.token.reset();
         self.state = State::Continue;
     }
 
     pub fn actor_ref(&self) -> &A {
-        &self.actor
+        self.actor.as_ref().unwrap()
     }
 
     pub fn actor_mut(&mut self) -> &mut A {
-        &mut self.actor
+        self.actor.as_mut().unwrap()
+    }
+
+    pub(crate) async fn start(&mut self) {
+        self.last_active = self.timer.now();
+        let actor = self.actor.as_mut().unwrap();
+        actor.starting(&self.context).await;
+    }
+
+    pub(crate) async fn stop(&mut self) {
+        let actor = self.actor.as_mut().unwrap();
+        actor.stopping(&self.context).await;
+    }
+
+    /// The envelopes waiting to be handled, in the order they will be handled
+    #[cfg(feature = "testing")]
+    pub(crate) fn queued(&mut self) -> impl Iterator<Item = &Envelope<A>> {
+        self.fill_backlog();
+        self.backlog.iter()
+    }
+
+    /// Applies any state change requested via the context or a [`ShutdownHandle`] which has not
+    /// yet been received, returning the resulting state
+    pub(crate) fn receive_state(&mut self) -> State {
+        while let Ok(state) = self.from_context.try_recv() {
+            self.apply(state);
+        }
+
+        self.state
+    }
+
+    fn apply(&mut self, state: State) {
+        match state {
+            State::Snapshot => self.take_snapshot(),
+            state => self.state = state,
+        }
+    }
+
+    /// Handle the next message which is ready, without waiting for one
+    ///
+    /// Returns `false` if there was nothing to handle, or shutdown has been requested.
+    #[cfg(feature = "testing")]
+    pub(crate) async fn step(&mut self) -> bool {
+        if self.receive_state() == State::Shutdown {
+            return false;
+        }
+
+        match self.next_ready() {
+            Some(env) => {
+                self.last_active = self.timer.now();
+                self.dispatch(env).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn take_snapshot(&mut self) {
+        if let Some(snapshots) = self.snapshots.as_mut() {
+            snapshots.take(self.actor.as_mut().unwrap(), self.timer.now());
+        }
+    }
+
+    /// The next envelope which can be handled without waiting
+    ///
+    /// Scheduled envelopes which are due take precedence, those taken from the mailbox which are
+    /// not yet due are held back.
+    fn next_ready(&mut self) -> Option<Envelope<A>> {
+        if let Some(env) = self.schedule.pop_due(self.timer.now()) {
+            return Some(env);
+        }
+
+        self.context.stash.replay_into(&mut self.backlog);
+        loop {
+            let env = match self.backlog.pop_front() {
+                Some(env) => env,
+                None => self.receiver.try_recv().ok()?,
+            };
+            if let Some(env) = self.accept(env) {
+                return Some(env);
+            }
+        }
+    }
+
+    /// Take an envelope from the mailbox, holding it back if its delivery is scheduled
+    fn accept(&mut self, mut env: Envelope<A>) -> Option<Envelope<A>> {
+        self.mailbox.release(&mut env);
+        match env.take_delivery() {
+            Some(delivery) => {
+                self.schedule.insert(env, delivery);
+                None
+            }
+            None => Some(env),
+        }
     }
 
     async fn continuation(&mut self) {
-        let fut1 = async { self.from_context.recv().await.map(|val| Race::State(val)) };
-        let fut2 = async { self.receiver.recv().await.map(|val| Race::Envelope(val)) };
+        crate::stage::cooperate().await;
+
+        if self.receive_state() != State::Continue {
+            return;
+        }
 
-        let result = crate::futures::race_biased(fut1, fut2).await;
+        let now = self.timer.now();
+        let next_snapshot = self.snapshots.as_mut().and_then(|s| s.next(now));
+        if next_snapshot.is_some_and(|at| at <= now) {
+            self.take_snapshot();
+            return;
+        }
+
+        if let Some(env) = self.next_ready() {
+            self.last_active = now;
+            self.dispatch(env).await;
+            return;
+        }
+
+        let next_due = self.schedule.next_deadline();
+        // Pending scheduled messages keep the actor from going idle, and once idle the mailbox is
+        // already closed
+        let idle = match next_due {
+            Some(_) => None,
+            None if self.receiver.is_closed() => None,
+            None => self.idle_timeout.map(|timeout| self.last_active + timeout),
+        };
+        let wake = [
+            next_due.map(|at| (at, Race::Due)),
+            next_snapshot.map(|at| (at, Race::Due)),
+            idle.map(|at| (at, Race::Idle)),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(at, _)| *at);
+
+        // An earlier sleep which is still pending is kept, waking early only costs a continuation
+        let race = match wake {
+            Some((at, race)) => {
+                if !self
+                    .wake
+                    .as_ref()
+                    .is_some_and(|wake| now < wake.at && wake.at <= at)
+                {
+                    let sleep = self.timer.sleep_until(at);
+                    self.wake = Some(Wake { at, sleep });
+                }
+                Some(race)
+            }
+            None => {
+                self.wake = None;
+                None
+            }
+        };
+
+        let (from_context, wake, receiver) = (&self.from_context, &mut self.wake, &self.receiver);
+        let fut1 = async { from_context.recv().await.map(|val| Race::State(val)) };
+        // The backlog was emptied by `next_ready`
+        let fut2 = async { receiver.recv().await.map(|val| Race::Envelope(val)) };
+        let fut3 = async {
+            match (wake, race) {
+                (Some(wake), Some(race)) => {
+                    wake.sleep.as_mut().await;
+                    Ok(race)
+                }
+                _ => std::future::pending().await,
+            }
+        };
+
+        // The mailbox is polled ahead of the timer, so a busy actor is never woken needlessly
+        let result =
+            crate::futures::race_biased(fut1, crate::futures::race_biased(fut2, fut3)).await;
 
         match result {
-            Ok(Race::State(state)) => self.state = state,
-            Ok(Race::Envelope(env)) => env.resolve(&mut self.actor, &self.context).await,
+            Ok(Race::State(state)) => self.apply(state),
+            // Later continuations drain the mailbox, then observe it as closed
+            Ok(Race::Idle) if self.is_idle() => {
+                self.receiver.close();
+                self.mailbox.close();
+            }
+            // Due messages and snapshots are picked up at the start of the next continuation, as
+            // is a sleep kept from before the idle deadline moved
+            Ok(Race::Due | Race::Idle) => (),
+            Ok(Race::Envelope(env)) => {
+                self.last_active = self.timer.now();
+                if let Some(env) = self.accept(env) {
+                    self.dispatch(env).await;
+                }
+            }
             Err(_) => {
                 self.state = State::SendersClosed;
             }
         }
     }
+
+    /// Whether the idle timeout has passed since the last message was received
+    fn is_idle(&self) -> bool {
+        self.idle_timeout
+            .is_some_and(|timeout| self.timer.now() >= self.last_active + timeout)
+    }
+
+    /// Gathers any further messages of the same type as `first` which are already waiting, up to
+    /// `limit`, preserving the order in which they were sent
+    ///
+    /// Envelopes of other types which are passed over in the mailbox are moved to the backlog,
+    /// where they keep taking up capacity until they are handled.
+    fn collect_batch(&mut self, first: Envelope<A>, limit: usize) -> Vec<Envelope<A>> {
+        let id = first.type_id();
+        let matches =
+            |env: &Envelope<A>| env.type_id() == id && !env.is_scheduled() && !env.is_request();
+        let mut batch = vec![first];
+
+        let mut i = 0;
+        while batch.len() < limit && i < self.backlog.len() {
+            if matches(&self.backlog[i]) {
+                let mut env = self.backlog.remove(i).unwrap();
+                self.mailbox.release(&mut env);
+                batch.extend(self.mailbox.coalescing.claim(env));
+            } else {
+                i += 1;
+            }
+        }
+
+        while batch.len() < limit {
+            match self.receiver.try_recv() {
+                Ok(mut env) if matches(&env) => {
+                    self.mailbox.release(&mut env);
+                    batch.extend(self.mailbox.coalescing.claim(env));
+                }
+                Ok(env) => self.backlog.push_back(env),
+                Err(_) => break,
+            }
+        }
+
+        batch
+    }
+
+    async fn dispatch(&mut self, env: Envelope<A>) {
+        let Some(env) = self.mailbox.coalescing.claim(env) else {
+            return;
+        };
+        let name = env.type_name();
+        let timeout = self.timeouts.get(env.type_id());
+        let forceful = self.forceful_shutdown;
+        // Kept so a request can still be failed once its handler has consumed it
+        let mut aborts = Vec::new();
+        // Requests are never batched, as each expects its own response
+        let batch = self
+            .batches
+            .get(env.type_id())
+            .filter(|_| !env.is_request());
+        let work = match batch {
+            Some(batch) => {
+                // A batch may have been sent from several actors, none of which is its sender
+                self.context.origin = None;
+                let mut envs = self.collect_batch(env, batch.limit);
+                self.context.headers = envs[0].take_headers();
+                aborts.extend(envs.iter().filter_map(Envelope::abort_handle));
+                Work::Batch(envs, batch)
+            }
+            None => {
+                let mut env = env;
+                self.context.origin = env.take_origin();
+                self.context.headers = env.take_headers();
+                aborts.extend(env.abort_handle());
+                Work::Single(env)
+            }
+        };
+
+        let origin = self.origin.clone();
+        let correlation_id = self.context.headers.shared_correlation_id();
+        let rejected = Rejected::default();
+        let handle = catch_unwind_future(async {
+            let actor = self.actor.as_mut().unwrap();
+            self.middleware
+                .resolve(work, actor, &self.context, &rejected)
+                .await
+        });
+        let handle = Scoped::new(Current::new(origin, correlation_id), handle);
+        let handle = async {
+            match handle.await {
+                Ok(()) => Outcome::Handled,
+                Err(payload) => Outcome::Panicked(payload),
+            }
+        };
+        let deadline = async {
+            match timeout {
+                Some(timeout) => self.timer.sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+            Outcome::TimedOut
+        };
+        let cancelled = async {
+            if forceful {
+                self.context.token.cancelled().await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+            Outcome::Cancelled
+        };
+
+        let race = crate::futures::race_biased(deadline, cancelled);
+        let outcome = crate::futures::race_biased(handle, race).await;
+        // Only set if a middleware dropped the work rather than handing it on
+        let rejected = rejected.into_inner().unwrap();
+        match outcome {
+            Outcome::TimedOut => {
+                let actor = self.actor.as_mut().unwrap();
+                actor.on_timeout(name, &self.context).await
+            }
+            Outcome::Handled => {
+                for env in rejected.into_iter().flat_map(Work::into_envelopes) {
+                    env.abort(AskError::Rejected);
+                    self.mailbox.dead_letter(env, DeadLetterReason::Rejected);
+                }
+            }
+            Outcome::Cancelled => (),
+            Outcome::Panicked(payload) => {
+                for abort in &aborts {
+                    abort.set(AskError::ActorPanicked);
+                }
+                match rejected {
+                    Some(work) => {
+                        for env in work.into_envelopes() {
+                            self.mailbox
+                                .dead_letter(env, DeadLetterReason::HandlerPanicked);
+                        }
+                    }
+                    // The message was consumed by the handler, only its type can be reported
+                    None => self
+                        .mailbox
+                        .report(name, None, DeadLetterReason::HandlerPanicked),
+                }
+                std::panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+impl<A> Drop for Executor<A> {
+    /// Reports every message still waiting to be handled as a dead letter
+    fn drop(&mut self) {
+        let pending = self.take_pending();
+        for env in pending.queued.into_iter().chain(pending.stashed) {
+            self.mailbox.dead_letter(env, DeadLetterReason::Shutdown);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use super::*;
 
     pub struct Foo;
 
     impl Actor for Foo {}
 
+    struct Hang;
+    struct Quick;
+
+    #[derive(Default)]
+    struct Slow {
+        timed_out: Vec<&'static str>,
+        quick: usize,
+    }
+
+    impl Actor for Slow {
+        async fn on_timeout(&mut self, message: &'static str, _ctx: &Context<Self>) {
+            self.timed_out.push(message);
+        }
+    }
+
+    impl crate::Handler<Hang> for Slow {
+        async fn handle(&mut self, _msg: Hang, _ctx: &Context<Self>) {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    impl crate::Handler<Quick> for Slow {
+        async fn handle(&mut self, _msg: Quick, _ctx: &Context<Self>) {
+            self.quick += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn hung_handler_times_out() {
+        let (mut executor, addr) = Executor::new(Slow::default());
+        executor.set_handler_timeout(Some(Duration::from_millis(20)));
+        addr.send(Hang).await;
+        addr.send(Quick).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        let actor = executor.actor_ref();
+        assert_eq!(actor.timed_out, vec![std::any::type_name::<Hang>()]);
+        assert_eq!(actor.quick, 1);
+    }
+
+    #[tokio::test]
+    async fn per_message_timeout_overrides_default() {
+        let (mut executor, addr) = Executor::new(Slow::default());
+        executor.set_handler_timeout_for::<Hang>(Some(Duration::from_millis(20)));
+        addr.send(Hang).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().timed_out.len(), 1);
+    }
+
+    struct Tick(usize);
+
+    #[derive(Default)]
+    struct Ticks(Vec<usize>);
+
+    impl Actor for Ticks {}
+
+    impl crate::Handler<Tick> for Ticks {
+        async fn handle(&mut self, msg: Tick, ctx: &Context<Self>) {
+            self.0.push(msg.0);
+            if self.0.len() == 2 {
+                ctx.shutdown();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn scheduled_messages_arrive_in_deadline_order() {
+        let (mut executor, addr) = Executor::new(Ticks::default());
+        addr.send_after(Tick(1), Duration::from_millis(60)).await;
+        addr.send_after(Tick(2), Duration::from_millis(20)).await;
+        addr.send(Tick(3)).await;
+
+        assert!(executor.run().await.is_ok());
+        assert_eq!(executor.actor_ref().0, vec![3, 2]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_schedule_is_not_delivered() {
+        let (mut executor, addr) = Executor::new(Ticks::default());
+        let handle = addr.send_after(Tick(1), Duration::from_millis(20)).await;
+        addr.send_at(Tick(2), Instant::now() + Duration::from_millis(40))
+            .await;
+        addr.send_after(Tick(3), Duration::from_millis(60)).await;
+        handle.cancel();
+
+        assert!(executor.run().await.is_ok());
+        assert_eq!(executor.actor_ref().0, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn pending_schedule_does_not_keep_actor_alive() {
+        let (mut executor, addr) = Executor::new(Ticks::default());
+        addr.send_after(Tick(1), Duration::from_secs(60)).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert!(executor.actor_ref().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn schedule_is_measured_from_sending_without_taking_capacity() {
+        let timer = crate::timer::ManualTimer::new();
+        let (mut executor, addr) = Executor::new_with_capacity(Ticks::default(), 1);
+        executor.set_timer(timer.clone());
+        addr.try_send(Tick(1));
+        assert!(addr.is_full());
+
+        // Would wait forever if scheduled messages took up capacity
+        addr.send_after(Tick(2), Duration::from_secs(10)).await;
+        assert_eq!(addr.len(), 1);
+
+        // The delay has passed before the executor ever sees the message
+        timer.advance(Duration::from_secs(10));
+        assert!(executor.run().await.is_ok());
+        assert_eq!(executor.actor_ref().0, vec![1, 2]);
+    }
+
+    struct Poll;
+
+    #[derive(Default)]
+    struct LongPoll {
+        bailed: bool,
+    }
+
+    impl Actor for LongPoll {}
+
+    impl crate::Handler<Poll> for LongPoll {
+        async fn handle(&mut self, _msg: Poll, ctx: &Context<Self>) {
+            let work = async {
+                std::future::pending::<()>().await;
+                false
+            };
+            let cancelled = async {
+                ctx.cancelled().await;
+                true
+            };
+            self.bailed = crate::futures::race_biased(work, cancelled).await;
+        }
+    }
+
+    impl crate::Handler<Hang> for LongPoll {
+        async fn handle(&mut self, _msg: Hang, _ctx: &Context<Self>) {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn handler_observes_cancellation() {
+        let (mut executor, addr) = Executor::new(LongPoll::default());
+        let handle = executor.shutdown_handle();
+        addr.send(Poll).await;
+
+        let task = tokio::spawn(async move {
+            let result = executor.run().await;
+            (result, executor.actor_ref().bailed)
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        handle.shutdown().unwrap();
+
+        let (result, bailed) = task.await.unwrap();
+        assert!(result.is_ok());
+        assert!(bailed);
+    }
+
+    #[tokio::test]
+    async fn forceful_shutdown_drops_handler() {
+        let (mut executor, addr) = Executor::new(LongPoll::default());
+        executor.set_forceful_shutdown(true);
+        let handle = executor.shutdown_handle();
+        addr.send(Hang).await;
+
+        let task = tokio::spawn(async move { executor.run().await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        handle.shutdown().unwrap();
+
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn shutdown_before_running_is_honored() {
+        let (mut executor, addr) = Executor::new(Ticks::default());
+        executor.shutdown_handle().shutdown().unwrap();
+        addr.send(Tick(1)).await;
+        assert!(executor.run().await.is_ok());
+        assert!(executor.actor_ref().0.is_empty());
+
+        // The executor can be run again, without the earlier shutdown carrying over
+        addr.send(Tick(2)).await;
+        assert!(executor.run().await.is_ok());
+        assert_eq!(executor.actor_ref().0, vec![1, 2]);
+    }
+
     #[tokio::test]
     async fn dropped_address_exits() {
         let (mut actor, addr) = Executor::new(Foo);
@@ -215,4 +1174,77 @@ mod tests {
         drop(addr);
         assert!(handle.await.unwrap().is_err())
     }
+
+    #[tokio::test]
+    async fn idle_executor_closes_mailbox() {
+        let (mut executor, addr) = Executor::new(Ticks::default());
+        executor.set_idle_timeout(Some(Duration::from_millis(20)));
+        addr.send(Tick(1)).await;
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().0, vec![1]);
+        assert!(addr.is_closed());
+    }
+
+    #[tokio::test]
+    async fn idle_sleep_is_kept_while_busy() {
+        struct Counting(
+            crate::timer::ManualTimer,
+            Arc<std::sync::atomic::AtomicUsize>,
+        );
+
+        impl Timer for Counting {
+            fn now(&self) -> Instant {
+                self.0.now()
+            }
+
+            fn sleep_until(&self, deadline: Instant) -> Sleep {
+                self.1.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                self.0.sleep_until(deadline)
+            }
+        }
+
+        #[derive(Default)]
+        struct Busy(Vec<usize>);
+
+        impl Actor for Busy {}
+
+        impl crate::Handler<Tick> for Busy {
+            async fn handle(&mut self, msg: Tick, _ctx: &Context<Self>) {
+                self.0.push(msg.0);
+            }
+        }
+
+        let timer = crate::timer::ManualTimer::new();
+        let sleeps = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (mut executor, addr) = Executor::new(Busy::default());
+        executor.set_timer(Counting(timer.clone(), sleeps.clone()));
+        executor.set_idle_timeout(Some(Duration::from_secs(10)));
+        let handle = tokio::spawn(async move {
+            let _ = executor.run().await;
+            executor
+        });
+
+        let settle = || async {
+            for _ in 0..5 {
+                tokio::task::yield_now().await;
+            }
+        };
+        for i in 0..5 {
+            timer.advance(Duration::from_secs(1));
+            addr.send(Tick(i)).await;
+            settle().await;
+        }
+        assert_eq!(sleeps.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // The first sleep fires early, and is replaced by one for the actual deadline
+        timer.advance(Duration::from_secs(6));
+        settle().await;
+        assert!(!addr.is_closed());
+        timer.advance(Duration::from_secs(5));
+        let executor = handle.await.unwrap();
+        assert!(addr.is_closed());
+        assert_eq!(executor.actor_ref().0, vec![0, 1, 2, 3, 4]);
+        assert_eq!(sleeps.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
 }
@@ -1,3 +1,7 @@
+mod block_on;
+mod catch_unwind;
 mod race;
 
+pub use block_on::block_on;
+pub use catch_unwind::catch_unwind_future;
 pub use race::race_biased;
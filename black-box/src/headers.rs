@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+
+use crate::scope::Current;
+
+/// Metadata carried alongside a message, read by its handler with
+/// [`Context::headers`](crate::Context::headers)
+///
+/// Headers are attached with [`Address::send_with_headers`](crate::Address::send_with_headers).
+/// A message sent from within a handler inherits the correlation id of the message being handled,
+/// unless it is given one of its own.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Transfer(u64);
+///
+/// struct Ledger;
+/// impl Actor for Ledger {}
+/// impl Handler<Transfer> for Ledger {
+///     async fn handle(&mut self, msg: Transfer, ctx: &Context<Self>) {
+///         let headers = ctx.headers();
+///         println!(
+///             "[{}] transfer of {} enqueued at {:?}",
+///             headers.correlation_id().unwrap_or("-"),
+///             msg.0,
+///             headers.enqueued_at(),
+///         );
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (mut executor, address) = Executor::new(Ledger);
+/// let mut headers = Headers::new();
+/// headers.set_correlation_id("req-7f3a");
+/// headers.insert("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+/// address.send_with_headers(Transfer(100), headers).await;
+/// # drop(address);
+/// # executor.run().await.unwrap_err();
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Headers {
+    enqueued_at: Option<Instant>,
+    correlation_id: Option<Arc<str>>,
+    entries: BTreeMap<String, String>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When the message was enqueued, according to the receiving executor's
+    /// [`Timer`](crate::Timer)
+    ///
+    /// This is `None` until the message has been enqueued.
+    pub fn enqueued_at(&self) -> Option<Instant> {
+        self.enqueued_at
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    pub fn set_correlation_id(&mut self, id: impl Into<Arc<str>>) {
+        self.correlation_id = Some(id.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Set a user defined entry, returning the previous value
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.entries.insert(key.into(), value.into())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// The user defined entries, ordered by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Headers for a message sent now, inheriting the correlation id of the message currently
+    /// being handled
+    pub(crate) fn inherited() -> Option<Self> {
+        let correlation_id = Current::correlation_id()?;
+        Some(Self {
+            correlation_id: Some(correlation_id),
+            ..Self::default()
+        })
+    }
+
+    /// Headers given by the sender, falling back on the inherited correlation id
+    pub(crate) fn inherit(mut self) -> Self {
+        if self.correlation_id.is_none() {
+            self.correlation_id = Current::correlation_id();
+        }
+        self
+    }
+
+    pub(crate) fn shared_correlation_id(&self) -> Option<Arc<str>> {
+        self.correlation_id.clone()
+    }
+
+    pub(crate) fn enqueued(enqueued_at: Option<Instant>) -> Self {
+        Self {
+            enqueued_at,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn set_enqueued_at(&mut self, enqueued_at: Instant) {
+        self.enqueued_at = Some(enqueued_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use crate::{
+        timer::{ManualTimer, Timer},
+        Actor, Address, Context, Executor, Handler,
+    };
+
+    use super::*;
+
+    struct Audit;
+
+    type Seen = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+    struct Auditor(Seen);
+    impl Actor for Auditor {}
+    impl Handler<Audit> for Auditor {
+        async fn handle(&mut self, _msg: Audit, ctx: &Context<Self>) {
+            let headers = ctx.headers();
+            assert!(headers.enqueued_at().is_some_and(|at| at <= Instant::now()));
+            let seen = (
+                headers.correlation_id().map(String::from),
+                headers.get("user").map(String::from),
+            );
+            self.0.lock().unwrap().push(seen);
+        }
+    }
+
+    struct Relay(Address<Auditor>);
+    impl Actor for Relay {}
+    impl Handler<Audit> for Relay {
+        async fn handle(&mut self, _msg: Audit, _ctx: &Context<Self>) {
+            self.0.send(Audit).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn correlation_ids_propagate_through_handlers() {
+        let seen = Seen::default();
+        let (mut auditor, auditor_addr) = Executor::new(Auditor(seen.clone()));
+        let (mut relay, relay_addr) = Executor::new(Relay(auditor_addr.clone()));
+
+        let mut headers = Headers::new();
+        headers.set_correlation_id("abc");
+        headers.insert("user", "alice");
+        auditor_addr.send_with_headers(Audit, headers.clone()).await;
+        relay_addr.send_with_headers(Audit, headers).await;
+        auditor_addr.send(Audit).await;
+
+        drop(relay_addr);
+        relay.run().await.unwrap_err();
+        drop((relay, auditor_addr));
+        auditor.run().await.unwrap_err();
+
+        let owned = |s: &str| Some(s.to_string());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (owned("abc"), owned("alice")),
+                (None, None),
+                // Only the correlation id is inherited
+                (owned("abc"), None),
+            ]
+        );
+    }
+
+    struct Stamped(Vec<Option<Instant>>);
+    impl Actor for Stamped {}
+    impl Handler<Audit> for Stamped {
+        async fn handle(&mut self, _msg: Audit, ctx: &Context<Self>) {
+            self.0.push(ctx.headers().enqueued_at());
+        }
+    }
+
+    #[tokio::test]
+    async fn enqueued_at_is_measured_by_the_timer() {
+        let timer = ManualTimer::new();
+        let (mut executor, addr) = Executor::new(Stamped(Vec::new()));
+        executor.set_timer(timer.clone());
+
+        let headers = Headers::new();
+        assert_eq!(headers.enqueued_at(), None);
+        let first = timer.now();
+        addr.send_with_headers(Audit, headers).await;
+        timer.advance(Duration::from_secs(5));
+        addr.send(Audit).await;
+        drop(addr);
+
+        executor.run().await.unwrap_err();
+        let second = first + Duration::from_secs(5);
+        assert_eq!(executor.actor_ref().0, [Some(first), Some(second)]);
+    }
+}
@@ -1,12 +1,59 @@
 #![doc = include_str!("../../README.md")]
 
 mod actors;
+mod batch;
+mod blocking;
+mod cancel;
+mod coalesce;
+mod dead_letter;
 pub mod error;
 mod executor;
 mod futures;
+mod headers;
+mod local;
+mod machine;
+mod mailbox;
 pub(crate) mod message;
+mod middleware;
+mod pending;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+mod pool;
+#[cfg(feature = "remote")]
+pub mod remote;
+mod reply;
+mod request;
+mod schedule;
+mod scope;
+mod shard;
+mod snapshot;
+mod stage;
+mod stash;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timer;
+mod view;
 
 pub use self::{
-    actors::{Actor, Address, Handler, WeakAddress},
+    actors::{Actor, ActorId, Address, Handler, WeakAddress},
+    batch::BatchHandler,
+    blocking::{SyncActor, SyncAddress, SyncContext, SyncExecutor, SyncHandler, WeakSyncAddress},
+    cancel::CancellationToken,
+    dead_letter::{DeadLetter, DeadLetterReason, DeadLetters},
     executor::{Context, Executor, ShutdownHandle},
+    headers::Headers,
+    local::{
+        LocalActor, LocalAddress, LocalContext, LocalExecutor, LocalHandler, WeakLocalAddress,
+    },
+    machine::{Behavior, BehaviorHandler, Machine, MachineMessage, Transition, Unhandled},
+    middleware::{Middleware, MiddlewareFuture, Next},
+    pending::Pending,
+    pool::{LeastLoaded, Pool, PoolAddress, Random, RoundRobin, Router},
+    request::{Request, RequestHandler},
+    schedule::ScheduleHandle,
+    shard::ShardedAddress,
+    snapshot::{DirectorySnapshotStore, InMemorySnapshotStore, Snapshot, SnapshotStore},
+    stage::{Stage, StageHandle},
+    timer::{Sleep, ThreadTimer, Timer},
+    view::{Allows, Permits, Position, View},
 };
@@ -0,0 +1,344 @@
+use std::future::Future;
+
+use async_channel::{Receiver, Sender};
+
+use crate::{
+    cancel::CancellationToken,
+    dead_letter::{DeadLetterReason, DeadLetters},
+    error::AddressError,
+    executor::{ShutdownHandle, State, DEFAULT_CAP},
+    message::LocalEnvelope,
+};
+
+use super::{LocalActor, LocalAddress, Sink, WeakLocalAddress};
+
+/// The [`Context`](crate::Context) counterpart for actors run on a [`LocalExecutor`]
+#[derive(Debug, Clone)]
+pub struct LocalContext<A> {
+    sender: Sender<State>,
+    address: WeakLocalAddress<A>,
+    token: CancellationToken,
+}
+
+impl<A> LocalContext<A> {
+    /// Triggers the end of the executor.
+    ///
+    /// Once triggered, no new messages will be processed and the actor will exit after resolving
+    /// [`LocalActor::stopping`]. Handlers currently awaiting [`Self::cancelled`] are woken.
+    pub fn shutdown(&self) {
+        let _ = self.sender.force_send(State::Shutdown);
+        self.token.cancel();
+    }
+
+    /// Resolves once shutdown of the actor has been requested
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Whether shutdown of the actor has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// A cloneable token which can be handed to tasks spawned from within a handler
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Retrieve the address for the executor's actor
+    ///
+    /// This is useful when an actor wants to emit messages to itself.
+    pub fn address(&self) -> &WeakLocalAddress<A> {
+        &self.address
+    }
+}
+
+/// The event loop for a [`LocalActor`]
+///
+/// The [`Executor`](crate::Executor) counterpart for actors and handlers which are not `Send`, such
+/// as actors holding `Rc`, `RefCell` or thread-bound handles. The future returned by
+/// [`LocalExecutor::run`] is not `Send` either, so it must be driven by a single-threaded executor
+/// like tokio's `LocalSet` or `async_executor::LocalExecutor`.
+///
+/// Messages are still required to be `Send`, as is the [`LocalAddress`], so other threads can post
+/// to a local actor.
+///
+/// Only the core event loop of the [`Executor`](crate::Executor) is provided. Handler timeouts,
+/// scheduled, stashed, coalesced and batched messages, middleware, snapshots, replies and headers
+/// are not supported.
+///
+/// # Example
+///
+/// ```no_run
+/// # use black_box::*;
+/// # use std::rc::Rc;
+/// struct MyActor(Rc<()>);
+/// impl LocalActor for MyActor {}
+///
+/// # async fn example() {
+/// let (mut executor, addr) = LocalExecutor::new(MyActor(Rc::new(())));
+///
+/// let local = tokio::task::LocalSet::new();
+/// local.spawn_local(async move { executor.run().await });
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LocalExecutor<A> {
+    actor: A,
+    context: LocalContext<A>,
+    state: State,
+    from_context: Receiver<State>,
+    receiver: Receiver<LocalEnvelope<A>>,
+    dead_letters: Sink,
+}
+
+impl<A> LocalExecutor<A> {
+    pub fn new(actor: A) -> (Self, LocalAddress<A>) {
+        Self::new_with_capacity(actor, DEFAULT_CAP)
+    }
+
+    pub fn new_with_capacity(actor: A, cap: usize) -> (Self, LocalAddress<A>) {
+        let (sender, receiver) = async_channel::bounded(cap);
+        let dead_letters = Sink::default();
+        let address = LocalAddress::new(sender, dead_letters.clone());
+        let (state_tx, state_rx) = async_channel::unbounded();
+        let me = Self {
+            actor,
+            receiver,
+            context: LocalContext {
+                sender: state_tx,
+                address: address.downgrade(),
+                token: Default::default(),
+            },
+            from_context: state_rx,
+            state: Default::default(),
+            dead_letters,
+        };
+
+        (me, address)
+    }
+
+    /// Construct a new shutdown handle to be able to remotely shutdown the actor
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.context.sender.clone(), self.context.token.clone())
+    }
+
+    pub fn actor_ref(&self) -> &A {
+        &self.actor
+    }
+
+    pub fn actor_mut(&mut self) -> &mut A {
+        &mut self.actor
+    }
+
+    /// Report this actor's undeliverable messages to `sink`, rather than the global sink set with
+    /// [`DeadLetters::set_global`]
+    pub fn set_dead_letters(&mut self, sink: DeadLetters) {
+        *self.dead_letters.lock().unwrap() = Some(sink);
+    }
+}
+
+enum Race<A> {
+    State(State),
+    Envelope(LocalEnvelope<A>),
+}
+
+impl<A> LocalExecutor<A>
+where
+    A: LocalActor,
+{
+    /// Runs the executor, returns `Ok(())` if the actor invoked shutdown manually, and `Err(_)`
+    /// if all addresses to the actor have been dropped
+    ///
+    /// A shutdown requested before the executor runs is honored once it starts.
+    pub async fn run(&mut self) -> Result<(), AddressError> {
+        self.actor.starting(&self.context).await;
+
+        let result = loop {
+            match self.state {
+                State::Continue | State::Snapshot => self.continuation().await,
+                State::Shutdown => break Ok(()),
+                State::SendersClosed => break Err(AddressError::Closed),
+            }
+        };
+
+        self.actor.stopping(&self.context).await;
+        self.reset_state();
+
+        result
+    }
+
+    /// Runs the executor, halting execution early if the provided future polls ready.
+    ///
+    /// Returns `Ok(true)` if the provided future resolved, and `Ok(false)` if the the actor was
+    /// shut down
+    pub async fn run_against<F>(&mut self, fut: F) -> Result<bool, AddressError>
+    where
+        F: Future<Output = ()>,
+    {
+        let fut1 = async { self.run().await.map(|_| false) };
+        let fut2 = async {
+            fut.await;
+            Ok(true)
+        };
+
+        crate::futures::race_biased(fut1, fut2).await
+    }
+
+    /// Discards any state changes left over from a run, so the executor can be run again
+    fn reset_state(&mut self) {
+        while self.from_context.try_recv().is_ok() {}
+        self.context.token.reset();
+        self.state = State::Continue;
+    }
+
+    async fn continuation(&mut self) {
+        let fut1 = async { self.from_context.recv().await.map(|val| Race::State(val)) };
+        let fut2 = async { self.receiver.recv().await.map(|val| Race::Envelope(val)) };
+
+        match crate::futures::race_biased(fut1, fut2).await {
+            Ok(Race::State(state)) => self.state = state,
+            Ok(Race::Envelope(env)) => env.resolve(&mut self.actor, &self.context).await,
+            Err(_) => {
+                self.state = State::SendersClosed;
+            }
+        }
+    }
+}
+
+impl<A> Drop for LocalExecutor<A> {
+    /// Reports every message still waiting to be handled as a dead letter
+    fn drop(&mut self) {
+        self.receiver.close();
+        let sink = self.dead_letters.lock().unwrap().clone();
+        while let Ok(env) = self.receiver.try_recv() {
+            env.dead_letter(
+                self.context.address.id,
+                sink.clone(),
+                DeadLetterReason::Shutdown,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::local::LocalHandler;
+
+    use super::*;
+
+    struct Push(usize);
+    struct Stop;
+
+    #[derive(Default)]
+    struct Shared {
+        seen: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl LocalActor for Shared {}
+
+    impl LocalHandler<Push> for Shared {
+        async fn handle(&mut self, msg: Push, _ctx: &LocalContext<Self>) {
+            let seen = self.seen.clone();
+            // Holding a `!Send` value across an await point
+            tokio::task::yield_now().await;
+            seen.borrow_mut().push(msg.0);
+        }
+    }
+
+    impl LocalHandler<Stop> for Shared {
+        async fn handle(&mut self, _msg: Stop, ctx: &LocalContext<Self>) {
+            ctx.shutdown();
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_on_local_set_with_remote_sender() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (mut executor, addr) = LocalExecutor::new(Shared { seen: seen.clone() });
+
+        let sender = std::thread::spawn(move || {
+            block_on(async {
+                addr.send(Push(1)).await;
+                addr.send(Push(2)).await;
+                addr.send(Stop).await;
+            })
+        });
+
+        let local = tokio::task::LocalSet::new();
+        let result = local.run_until(async move { executor.run().await }).await;
+        sender.join().unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(*seen.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn runs_on_async_executor() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (mut executor, addr) = LocalExecutor::new(Shared { seen: seen.clone() });
+        addr.try_send(Push(7));
+        drop(addr);
+
+        let local = async_executor::LocalExecutor::new();
+        let result = block_on(local.run(async move { executor.run().await }));
+
+        assert!(result.is_err());
+        assert_eq!(*seen.borrow(), vec![7]);
+    }
+
+    #[test]
+    fn shutdown_before_running_is_honored() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let (mut executor, addr) = LocalExecutor::new(Shared { seen: seen.clone() });
+        executor.shutdown_handle().shutdown().unwrap();
+        addr.try_send(Push(1));
+        assert!(block_on(executor.run()).is_ok());
+        assert!(seen.borrow().is_empty());
+
+        // The executor can be run again, without the earlier shutdown carrying over
+        addr.try_send(Stop);
+        assert!(block_on(executor.run()).is_ok());
+        assert_eq!(*seen.borrow(), vec![1]);
+    }
+
+    #[test]
+    fn undeliverable_messages_are_reported() {
+        use std::sync::{Arc, Mutex};
+
+        let (mut executor, addr) = LocalExecutor::new_with_capacity(Shared::default(), 1);
+        let letters = Arc::new(Mutex::new(Vec::new()));
+        let sink = letters.clone();
+        executor.set_dead_letters(DeadLetters::new(move |letter| {
+            sink.lock().unwrap().push(letter);
+        }));
+
+        addr.try_send(Push(1));
+        addr.try_send(Push(2));
+        drop(executor);
+        block_on(addr.send(Push(3)));
+
+        use DeadLetterReason::*;
+        let letters = std::mem::take(&mut *letters.lock().unwrap());
+        let reported = letters
+            .into_iter()
+            .map(|letter| {
+                assert_eq!(letter.target(), addr.id());
+                (letter.reason(), letter.downcast::<Push>().unwrap().0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reported,
+            [(MailboxFull, 2), (Shutdown, 1), (MailboxClosed, 3)]
+        );
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(fut)
+    }
+}
@@ -0,0 +1,168 @@
+mod executor;
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::{Sender, WeakSender};
+
+use crate::{
+    actors::next_address_id,
+    dead_letter::{DeadLetterReason, DeadLetters},
+    message::{LocalEnvelope, Message},
+    ActorId,
+};
+
+/// The dead letter sink of a local actor, shared by its executor and addresses
+pub(crate) type Sink = Arc<Mutex<Option<DeadLetters>>>;
+
+pub use executor::{LocalContext, LocalExecutor};
+
+/// The [`Actor`](crate::Actor) counterpart for actors run on a [`LocalExecutor`]
+pub trait LocalActor: Sized {
+    fn starting(&mut self, _ctx: &LocalContext<Self>) -> impl Future<Output = ()> {
+        std::future::ready(())
+    }
+
+    fn stopping(&mut self, _ctx: &LocalContext<Self>) -> impl Future<Output = ()> {
+        std::future::ready(())
+    }
+}
+
+/// The [`Handler`](crate::Handler) counterpart for actors run on a [`LocalExecutor`]
+///
+/// Unlike [`Handler`](crate::Handler), the returned future is not required to be `Send`.
+pub trait LocalHandler<M>
+where
+    Self: LocalActor,
+    M: Message,
+{
+    /// Asynchronously act on the message, with mutable access to self
+    fn handle(&mut self, msg: M, ctx: &LocalContext<Self>) -> impl Future<Output = ()>;
+}
+
+/// A cloneable address which can be used to send messages to the associated [`LocalActor`]
+///
+/// The address is `Send` and `Sync` regardless of the actor, so it can be handed to other threads.
+#[derive(Debug)]
+pub struct LocalAddress<A> {
+    id: ActorId,
+    sender: Sender<LocalEnvelope<A>>,
+    sink: Sink,
+}
+
+impl<A> PartialEq for LocalAddress<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<A> Clone for LocalAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            sender: self.sender.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<A> LocalAddress<A> {
+    pub(crate) fn new(sender: Sender<LocalEnvelope<A>>, sink: Sink) -> Self {
+        Self {
+            id: ActorId(next_address_id()),
+            sender,
+            sink,
+        }
+    }
+
+    /// The id of the actor this address sends to
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    pub fn downgrade(&self) -> WeakLocalAddress<A> {
+        WeakLocalAddress {
+            id: self.id,
+            sender: self.sender.downgrade(),
+            sink: self.sink.clone(),
+        }
+    }
+
+    fn dead_letter(&self, env: LocalEnvelope<A>, reason: DeadLetterReason) {
+        let sink = self.sink.lock().unwrap().clone();
+        env.dead_letter(self.id, sink, reason);
+    }
+}
+
+impl<A> LocalAddress<A>
+where
+    A: 'static + LocalActor,
+{
+    /// Send the given message to the actor's receiver.
+    ///
+    /// If the receiver is currently full, it will await capacity to enqueue the message. If the
+    /// actor has stopped the message is reported as a [`DeadLetter`](crate::DeadLetter).
+    pub async fn send<M>(&self, message: M)
+    where
+        A: LocalHandler<M>,
+        M: Message,
+    {
+        let env = LocalEnvelope::pack(message);
+
+        if let Err(err) = self.sender.send(env).await {
+            self.dead_letter(err.0, DeadLetterReason::MailboxClosed);
+        }
+    }
+
+    /// Send the given message only if there is capacity to enqueue it immediately
+    ///
+    /// A message which cannot be enqueued is reported as a [`DeadLetter`](crate::DeadLetter).
+    pub fn try_send<M>(&self, message: M)
+    where
+        A: LocalHandler<M>,
+        M: Message,
+    {
+        let env = LocalEnvelope::pack(message);
+
+        match self.sender.try_send(env) {
+            Ok(()) => (),
+            Err(async_channel::TrySendError::Full(env)) => {
+                self.dead_letter(env, DeadLetterReason::MailboxFull)
+            }
+            Err(async_channel::TrySendError::Closed(env)) => {
+                self.dead_letter(env, DeadLetterReason::MailboxClosed)
+            }
+        }
+    }
+}
+
+/// A weak reference to a [`LocalAddress`], which does not keep the actor alive
+#[derive(Debug)]
+pub struct WeakLocalAddress<A> {
+    id: ActorId,
+    sender: WeakSender<LocalEnvelope<A>>,
+    sink: Sink,
+}
+
+impl<A> Clone for WeakLocalAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            sender: self.sender.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<A> WeakLocalAddress<A> {
+    pub fn upgrade(&self) -> Option<LocalAddress<A>> {
+        let sender = self.sender.upgrade()?;
+        Some(LocalAddress {
+            id: self.id,
+            sender,
+            sink: self.sink.clone(),
+        })
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+};
+
+use crate::{dead_letter::DeadLetterReason, executor::Context, message::Message, Actor, Handler};
+
+/// A state of a [`Machine`], with access to the machine's shared data `D`
+pub trait Behavior<D>: Send + 'static {}
+
+/// A message which can be sent to a [`Machine`] with shared data `D`
+///
+/// This declares the messages the machine accepts in any of its states, so sending any other
+/// message to it is a compile error. Whether the current state handles a declared message is only
+/// known once it arrives, see [`Unhandled`].
+pub trait MachineMessage<D>: Message {}
+
+/// The implementation for how a [`Behavior`] handles a particular message
+///
+/// This is the counterpart of [`Handler`] for a single state of a [`Machine`]. Only messages the
+/// current state has a registered handler for are accepted, see [`Unhandled`] for what happens to
+/// the rest.
+pub trait BehaviorHandler<M, D>: Behavior<D>
+where
+    M: MachineMessage<D>,
+{
+    /// Asynchronously act on the message, returning the state the machine moves to
+    fn handle(
+        &mut self,
+        msg: M,
+        data: &mut D,
+        ctx: &Context<Machine<D>>,
+    ) -> impl Future<Output = Transition<D>> + Send;
+}
+
+/// The state a [`Machine`] moves to after handling a message
+#[must_use]
+pub struct Transition<D> {
+    kind: Kind,
+    data: PhantomData<fn(D)>,
+}
+
+enum Kind {
+    Stay,
+    Become(Current),
+    Push(Current),
+    Pop,
+}
+
+impl<D> Transition<D> {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            data: PhantomData,
+        }
+    }
+
+    /// Remain in the current state
+    pub fn stay() -> Self {
+        Self::new(Kind::Stay)
+    }
+
+    /// Replace the current state
+    pub fn to<S: Behavior<D>>(state: S) -> Self {
+        Self::new(Kind::Become(Current::new(state)))
+    }
+
+    /// Enter a new state, keeping the current one so it can be returned to with [`Self::pop`]
+    pub fn push<S: Behavior<D>>(state: S) -> Self {
+        Self::new(Kind::Push(Current::new(state)))
+    }
+
+    /// Return to the state which was current before the last [`Self::push`]
+    ///
+    /// Behaves like [`Self::stay`] if there is no such state.
+    pub fn pop() -> Self {
+        Self::new(Kind::Pop)
+    }
+}
+
+impl<D> std::fmt::Debug for Transition<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            Kind::Stay => f.write_str("Stay"),
+            Kind::Become(state) => f.debug_tuple("Become").field(&state.name).finish(),
+            Kind::Push(state) => f.debug_tuple("Push").field(&state.name).finish(),
+            Kind::Pop => f.write_str("Pop"),
+        }
+    }
+}
+
+/// What a [`Machine`] does with a message its current state has no handler for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Unhandled {
+    /// Discard the message
+    Drop,
+    /// Stash the message with [`Context::stash`], every stashed message is replayed whenever the
+    /// machine changes state
+    Stash,
+    /// Report the message as a [`DeadLetter`](crate::DeadLetter), with
+    /// [`DeadLetterReason::Unhandled`], the default
+    #[default]
+    DeadLetter,
+}
+
+struct Current {
+    state: Box<dyn Any + Send>,
+    id: TypeId,
+    name: &'static str,
+}
+
+impl Current {
+    fn new<S: Any + Send>(state: S) -> Self {
+        Self {
+            state: Box::new(state),
+            id: TypeId::of::<S>(),
+            name: std::any::type_name::<S>(),
+        }
+    }
+}
+
+type Dispatch<D> = for<'a> fn(
+    &'a mut (dyn Any + Send),
+    &'a mut D,
+    Box<dyn Any + Send>,
+    &'a Context<Machine<D>>,
+) -> Pin<Box<dyn Future<Output = Transition<D>> + Send + 'a>>;
+
+fn dispatch<'a, D, S, M>(
+    state: &'a mut (dyn Any + Send),
+    data: &'a mut D,
+    msg: Box<dyn Any + Send>,
+    ctx: &'a Context<Machine<D>>,
+) -> Pin<Box<dyn Future<Output = Transition<D>> + Send + 'a>>
+where
+    D: Send + 'static,
+    S: BehaviorHandler<M, D>,
+    M: MachineMessage<D>,
+{
+    // Handlers are looked up by the state's TypeId, so these cannot fail
+    let state = state.downcast_mut::<S>().unwrap();
+    let msg = *msg.downcast::<M>().unwrap();
+    Box::pin(state.handle(msg, data, ctx))
+}
+
+/// An actor whose accepted messages depend on its current state
+///
+/// Each state is its own type implementing [`Behavior`], with a [`BehaviorHandler`] for each
+/// message it accepts, which must also be registered with [`Self::register`]. Handlers return a
+/// [`Transition`] to move the machine between states. Data shared by every state lives in `D`,
+/// and every message sent to the machine is declared with [`MachineMessage`].
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Connected;
+/// struct Write(Vec<u8>);
+///
+/// impl MachineMessage<Vec<u8>> for Connected {}
+/// impl MachineMessage<Vec<u8>> for Write {}
+///
+/// struct Connecting;
+/// struct Ready;
+///
+/// impl Behavior<Vec<u8>> for Connecting {}
+/// impl Behavior<Vec<u8>> for Ready {}
+///
+/// impl BehaviorHandler<Connected, Vec<u8>> for Connecting {
+///     async fn handle(
+///         &mut self,
+///         _msg: Connected,
+///         _sent: &mut Vec<u8>,
+///         _ctx: &Context<Machine<Vec<u8>>>,
+///     ) -> Transition<Vec<u8>> {
+///         Transition::to(Ready)
+///     }
+/// }
+///
+/// impl BehaviorHandler<Write, Vec<u8>> for Ready {
+///     async fn handle(
+///         &mut self,
+///         msg: Write,
+///         sent: &mut Vec<u8>,
+///         _ctx: &Context<Machine<Vec<u8>>>,
+///     ) -> Transition<Vec<u8>> {
+///         sent.extend(msg.0);
+///         Transition::stay()
+///     }
+/// }
+///
+/// let mut machine = Machine::new(Vec::new(), Connecting);
+/// machine.register::<Connecting, Connected>();
+/// machine.register::<Ready, Write>();
+/// // Writes sent while still connecting are held until the connection is ready
+/// machine.set_unhandled(Unhandled::Stash);
+///
+/// let (mut executor, addr) = Executor::new(machine);
+/// ```
+pub struct Machine<D> {
+    data: D,
+    /// The current state is last, below it are those entered with [`Transition::push`]
+    states: Vec<Current>,
+    handlers: HashMap<(TypeId, TypeId), Dispatch<D>>,
+    unhandled: Unhandled,
+}
+
+impl<D: std::fmt::Debug> std::fmt::Debug for Machine<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Machine")
+            .field("data", &self.data)
+            .field("state", &self.states.last().map(|state| state.name))
+            .field("unhandled", &self.unhandled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D> Machine<D>
+where
+    D: Send + 'static,
+{
+    pub fn new<S: Behavior<D>>(data: D, initial: S) -> Self {
+        Self {
+            data,
+            states: vec![Current::new(initial)],
+            handlers: HashMap::new(),
+            unhandled: Unhandled::default(),
+        }
+    }
+
+    /// Accept messages of type `M` while in state `S`
+    pub fn register<S, M>(&mut self)
+    where
+        S: BehaviorHandler<M, D>,
+        M: MachineMessage<D>,
+    {
+        let key = (TypeId::of::<S>(), TypeId::of::<M>());
+        self.handlers.insert(key, dispatch::<D, S, M>);
+    }
+
+    /// Set what happens to messages the current state does not accept, by default they are
+    /// reported as dead letters
+    pub fn set_unhandled(&mut self, unhandled: Unhandled) {
+        self.unhandled = unhandled;
+    }
+
+    pub fn data(&self) -> &D {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut D {
+        &mut self.data
+    }
+
+    /// Whether the machine is currently in state `S`
+    pub fn is_in<S: Behavior<D>>(&self) -> bool {
+        self.current().id == TypeId::of::<S>()
+    }
+
+    /// The type name of the current state
+    pub fn state_name(&self) -> &'static str {
+        self.current().name
+    }
+
+    fn current(&self) -> &Current {
+        // A machine is constructed with a state, and the last is never popped
+        self.states.last().unwrap()
+    }
+
+    /// Returns whether the state changed
+    fn transition(&mut self, transition: Transition<D>) -> bool {
+        match transition.kind {
+            Kind::Stay => return false,
+            Kind::Become(state) => *self.states.last_mut().unwrap() = state,
+            Kind::Push(state) => self.states.push(state),
+            Kind::Pop if self.states.len() > 1 => drop(self.states.pop()),
+            Kind::Pop => return false,
+        }
+
+        true
+    }
+}
+
+impl<D> Actor for Machine<D> where D: Send + 'static {}
+
+impl<D, M> Handler<M> for Machine<D>
+where
+    D: Send + 'static,
+    M: MachineMessage<D>,
+{
+    async fn handle(&mut self, msg: M, ctx: &Context<Self>) {
+        let key = (self.current().id, TypeId::of::<M>());
+        let Some(dispatch) = self.handlers.get(&key).copied() else {
+            match self.unhandled {
+                Unhandled::Drop => (),
+                Unhandled::Stash => ctx.stash(msg),
+                Unhandled::DeadLetter => ctx.address().mailbox().report(
+                    std::any::type_name::<M>(),
+                    Some(Box::new(msg)),
+                    DeadLetterReason::Unhandled,
+                ),
+            }
+            return;
+        };
+
+        let state = &mut *self.states.last_mut().unwrap().state;
+        let transition = dispatch(state, &mut self.data, Box::new(msg), ctx).await;
+        if self.transition(transition) {
+            ctx.unstash_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{DeadLetters, Executor};
+
+    use super::*;
+
+    struct Connected;
+    struct Write(&'static str);
+    struct Drain;
+    struct Drained;
+
+    struct Connecting;
+    struct Ready;
+    struct Draining;
+
+    impl MachineMessage<Vec<&'static str>> for Connected {}
+    impl MachineMessage<Vec<&'static str>> for Write {}
+    impl MachineMessage<Vec<&'static str>> for Drain {}
+    impl MachineMessage<Vec<&'static str>> for Drained {}
+
+    impl Behavior<Vec<&'static str>> for Connecting {}
+    impl Behavior<Vec<&'static str>> for Ready {}
+    impl Behavior<Vec<&'static str>> for Draining {}
+
+    type Ctx = Context<Machine<Vec<&'static str>>>;
+    type Next = Transition<Vec<&'static str>>;
+
+    impl BehaviorHandler<Connected, Vec<&'static str>> for Connecting {
+        async fn handle(&mut self, _: Connected, _: &mut Vec<&'static str>, _: &Ctx) -> Next {
+            Transition::to(Ready)
+        }
+    }
+
+    impl BehaviorHandler<Write, Vec<&'static str>> for Ready {
+        async fn handle(&mut self, msg: Write, sent: &mut Vec<&'static str>, _: &Ctx) -> Next {
+            sent.push(msg.0);
+            Transition::stay()
+        }
+    }
+
+    impl BehaviorHandler<Drain, Vec<&'static str>> for Ready {
+        async fn handle(&mut self, _: Drain, _: &mut Vec<&'static str>, _: &Ctx) -> Next {
+            Transition::push(Draining)
+        }
+    }
+
+    impl BehaviorHandler<Drained, Vec<&'static str>> for Draining {
+        async fn handle(&mut self, _: Drained, _: &mut Vec<&'static str>, _: &Ctx) -> Next {
+            Transition::pop()
+        }
+    }
+
+    fn connection() -> Machine<Vec<&'static str>> {
+        let mut machine = Machine::new(Vec::new(), Connecting);
+        machine.register::<Connecting, Connected>();
+        machine.register::<Ready, Write>();
+        machine.register::<Ready, Drain>();
+        machine.register::<Draining, Drained>();
+        machine
+    }
+
+    #[tokio::test]
+    async fn unhandled_messages_are_stashed_until_transition() {
+        let mut machine = connection();
+        machine.set_unhandled(Unhandled::Stash);
+        let (mut executor, addr) = Executor::new(machine);
+        addr.send(Write("a")).await;
+        addr.send(Connected).await;
+        addr.send(Drain).await;
+        addr.send(Write("b")).await;
+        addr.send(Drained).await;
+        addr.send(Write("c")).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        let machine = executor.actor_ref();
+        assert!(machine.is_in::<Ready>());
+        assert_eq!(*machine.data(), ["a", "b", "c"]);
+    }
+
+    /// Sends a write before connecting, and a second connect once connected
+    async fn run_out_of_order(
+        machine: Machine<Vec<&'static str>>,
+    ) -> (Vec<&'static str>, Vec<&'static str>) {
+        let dead = Arc::new(Mutex::new(Vec::new()));
+        let (mut executor, addr) = Executor::new(machine);
+        let sink_dead = dead.clone();
+        executor.set_dead_letters(DeadLetters::new(move |letter| {
+            assert_eq!(letter.reason(), DeadLetterReason::Unhandled);
+            sink_dead.lock().unwrap().push(letter.type_name());
+        }));
+        addr.send(Write("a")).await;
+        addr.send(Connected).await;
+        addr.send(Connected).await;
+        addr.send(Write("b")).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        let sent = executor.actor_ref().data().clone();
+        let dead = dead.lock().unwrap().clone();
+        (sent, dead)
+    }
+
+    #[tokio::test]
+    async fn unhandled_messages_are_dead_lettered_by_default() {
+        let (sent, dead) = run_out_of_order(connection()).await;
+        assert_eq!(sent, ["b"]);
+        assert_eq!(
+            dead,
+            [
+                std::any::type_name::<Write>(),
+                std::any::type_name::<Connected>()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unhandled_messages_can_be_dropped() {
+        let mut machine = connection();
+        machine.set_unhandled(Unhandled::Drop);
+        let (sent, dead) = run_out_of_order(machine).await;
+        assert_eq!(sent, ["b"]);
+        assert!(dead.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use event_listener::{Event, Listener};
+
+use crate::{
+    actors::next_address_id,
+    coalesce::Coalescing,
+    dead_letter::{DeadLetter, DeadLetterReason, DeadLetters},
+    message::Envelope,
+    timer::SharedTimer,
+    ActorId,
+};
+
+/// State shared by every address of an actor and its executor
+#[derive(Debug)]
+pub(crate) struct Mailbox {
+    id: ActorId,
+    pub(crate) coalescing: Coalescing,
+    /// Set by [`Address::close`](crate::Address::close), or once the executor stops receiving
+    closed: AtomicBool,
+    /// Messages waiting to be handled, counted against `capacity`
+    ///
+    /// The channel itself is unbounded, so messages the executor takes ahead of their turn keep
+    /// counting until they are handled, and scheduled messages never count.
+    queued: AtomicUsize,
+    capacity: Option<usize>,
+    /// Notified as capacity is released, or the mailbox is closed
+    released: Event,
+    /// Takes precedence over the global sink
+    dead_letters: Mutex<Option<DeadLetters>>,
+    /// The executor's timer, used by [`Address::ask_timeout`](crate::Address::ask_timeout)
+    timer: Mutex<SharedTimer>,
+}
+
+impl Mailbox {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self {
+            id: ActorId(next_address_id()),
+            coalescing: Coalescing::default(),
+            closed: AtomicBool::new(false),
+            queued: AtomicUsize::new(0),
+            capacity,
+            released: Event::new(),
+            dead_letters: Mutex::new(None),
+            timer: Mutex::new(SharedTimer::default()),
+        }
+    }
+
+    pub(crate) fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Stop accepting messages, waking any sender waiting for capacity
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.released.notify(usize::MAX);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.len() >= capacity)
+    }
+
+    /// Count the envelope against the capacity if there is room for it
+    pub(crate) fn try_acquire<A>(&self, env: &mut Envelope<A>) -> bool {
+        let acquired = self
+            .queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                match self.capacity {
+                    Some(capacity) if queued >= capacity => None,
+                    _ => Some(queued + 1),
+                }
+            })
+            .is_ok();
+        env.set_counted(acquired);
+        acquired
+    }
+
+    /// Wait for room to count the envelope against the capacity, returns `false` if the mailbox
+    /// is closed first
+    pub(crate) async fn acquire<A>(&self, env: &mut Envelope<A>) -> bool {
+        loop {
+            if self.is_closed() {
+                return false;
+            }
+            if self.try_acquire(env) {
+                return true;
+            }
+
+            let listener = self.released.listen();
+
+            // Capacity may have been released between the check and registering the listener
+            if self.is_closed() {
+                return false;
+            }
+            if self.try_acquire(env) {
+                return true;
+            }
+
+            listener.await;
+        }
+    }
+
+    /// As with [`Self::acquire`], blocking the current thread
+    pub(crate) fn acquire_blocking<A>(&self, env: &mut Envelope<A>) -> bool {
+        loop {
+            if self.is_closed() {
+                return false;
+            }
+            if self.try_acquire(env) {
+                return true;
+            }
+
+            let listener = self.released.listen();
+            if self.is_closed() {
+                return false;
+            }
+            if self.try_acquire(env) {
+                return true;
+            }
+
+            listener.wait();
+        }
+    }
+
+    /// Stop counting the envelope against the capacity, once it is no longer waiting to be handled
+    pub(crate) fn release<A>(&self, env: &mut Envelope<A>) {
+        if env.take_counted() {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            self.released.notify(1);
+        }
+    }
+
+    pub(crate) fn timer(&self) -> SharedTimer {
+        self.timer.lock().unwrap().clone()
+    }
+
+    /// The current instant according to the executor's timer
+    pub(crate) fn now(&self) -> Instant {
+        self.timer.lock().unwrap().now()
+    }
+
+    pub(crate) fn set_timer(&self, timer: SharedTimer) {
+        *self.timer.lock().unwrap() = timer;
+    }
+
+    pub(crate) fn dead_letters(&self) -> Option<DeadLetters> {
+        self.dead_letters.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_dead_letters(&self, sink: Option<DeadLetters>) {
+        *self.dead_letters.lock().unwrap() = sink;
+    }
+
+    /// Report an envelope which could not be delivered
+    pub(crate) fn dead_letter<A>(&self, env: Envelope<A>, reason: DeadLetterReason) {
+        // An empty coalesced envelope may have had its content claimed or withdrawn already
+        let env = match self.coalescing.claim(env) {
+            Some(env) => env,
+            None => return,
+        };
+        let type_name = env.type_name();
+        self.report(type_name, Some(env.into_content()), reason);
+    }
+
+    /// Report a message which could not be delivered, `message` is `None` if it was consumed
+    pub(crate) fn report(
+        &self,
+        type_name: &'static str,
+        message: Option<Box<dyn std::any::Any + Send>>,
+        reason: DeadLetterReason,
+    ) {
+        let sink = self.dead_letters.lock().unwrap().clone();
+        let letter = DeadLetter::new(type_name, self.id, reason, message);
+        DeadLetters::deliver(sink, letter);
+    }
+}
@@ -1,6 +1,22 @@
-use std::{any::Any, future::Future, pin::Pin};
+use std::{
+    any::{Any, TypeId},
+    future::Future,
+    pin::Pin,
+    time::Instant,
+};
 
-use crate::{executor::Context, Handler};
+use crate::{
+    blocking::{SyncContext, SyncHandler},
+    dead_letter::{DeadLetter, DeadLetterReason, DeadLetters},
+    error::AskError,
+    executor::Context,
+    headers::Headers,
+    local::{LocalContext, LocalHandler},
+    reply::Origin,
+    request::{Abort, Request, RequestHandler, Responder},
+    schedule::Delivery,
+    ActorId, Handler,
+};
 
 pub trait Message: 'static + Send {}
 
@@ -16,8 +32,53 @@ type FutType<A> = Box<
 >;
 
 pub(crate) struct Envelope<A> {
+    type_id: TypeId,
+    type_name: &'static str,
     content: Box<dyn Any + Send>,
     mapping: FutType<A>,
+    /// Boxed as most messages are not scheduled
+    delivery: Option<Box<Delivery>>,
+    /// The content is held by the mailbox's [`Coalescing`](crate::coalesce::Coalescing) slot
+    coalesced: bool,
+    /// Counted against the mailbox capacity until it is handled
+    counted: bool,
+    origin: Option<Origin>,
+    /// Stamped from the mailbox's timer as the envelope is enqueued
+    enqueued_at: Option<Instant>,
+    /// Only allocated when there is more to carry than the enqueue timestamp
+    headers: Option<Box<Headers>>,
+    /// Set for requests, to fail the ask if the request is dropped unanswered
+    abort: Option<Abort>,
+}
+
+/// The parts of an envelope which come from its sender, held apart while it is coalesced so a
+/// later send replaces all of them
+pub(crate) struct Contents {
+    message: Box<dyn Any + Send>,
+    origin: Option<Origin>,
+    enqueued_at: Option<Instant>,
+    headers: Option<Box<Headers>>,
+}
+
+impl Contents {
+    /// See [`Envelope::stamp`]
+    pub(crate) fn stamp(&mut self, enqueued_at: Instant) {
+        self.enqueued_at = Some(enqueued_at);
+        if let Some(headers) = &mut self.headers {
+            headers.set_enqueued_at(enqueued_at);
+        }
+    }
+}
+
+impl<A> std::fmt::Debug for Envelope<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Envelope")
+            .field("type_name", &self.type_name)
+            .field("delivery", &self.delivery)
+            .field("coalesced", &self.coalesced)
+            .field("origin", &self.origin)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<A> Envelope<A> {
@@ -33,7 +94,171 @@ impl<A> Envelope<A> {
         });
         let mapping = Box::new(mapping);
 
-        Self { content, mapping }
+        Self {
+            type_id: TypeId::of::<M>(),
+            type_name: std::any::type_name::<M>(),
+            content,
+            mapping,
+            delivery: None,
+            coalesced: false,
+            counted: false,
+            origin: Origin::current(),
+            enqueued_at: None,
+            headers: Headers::inherited().map(Box::new),
+            abort: None,
+        }
+    }
+
+    /// Pack a request, whose response is sent to `reply` once handled
+    pub(crate) fn pack_request<M>(message: M, reply: Responder<M::Response>) -> Self
+    where
+        M: Request,
+        A: 'static + RequestHandler<M> + Send,
+    {
+        let content: Box<dyn Any + Send> = Box::new(message);
+        let abort = reply.abort();
+        let mapping = Self::constrain(move |actor, msg, ctx| {
+            let message = Self::unpack(msg);
+            Box::pin(async move {
+                let response = actor.respond(message, ctx).await;
+                reply.send(response);
+            })
+        });
+
+        Self {
+            type_id: TypeId::of::<M>(),
+            type_name: std::any::type_name::<M>(),
+            content,
+            mapping,
+            delivery: None,
+            coalesced: false,
+            counted: false,
+            origin: Origin::current(),
+            enqueued_at: None,
+            headers: Headers::inherited().map(Box::new),
+            abort: Some(abort),
+        }
+    }
+
+    /// Fail the ask with `error` once the request is dropped unanswered, a no-op for other messages
+    pub(crate) fn abort(&self, error: AskError) {
+        if let Some(abort) = &self.abort {
+            abort.set(error);
+        }
+    }
+
+    /// A handle to fail the ask after the request has been handed to its handler, `None` for
+    /// other messages
+    pub(crate) fn abort_handle(&self) -> Option<Abort> {
+        self.abort.clone()
+    }
+
+    /// The actor which sent the message, if it was sent from within a handler
+    pub(crate) fn take_origin(&mut self) -> Option<Origin> {
+        self.origin.take()
+    }
+
+    pub(crate) fn set_origin(&mut self, origin: Option<Origin>) {
+        self.origin = origin;
+    }
+
+    pub(crate) fn take_headers(&mut self) -> Headers {
+        match self.headers.take() {
+            Some(headers) => *headers,
+            None => Headers::enqueued(self.enqueued_at),
+        }
+    }
+
+    pub(crate) fn set_headers(&mut self, headers: Headers) {
+        self.headers = Some(Box::new(headers));
+    }
+
+    /// Record when the envelope was enqueued, replacing any earlier stamp
+    pub(crate) fn stamp(&mut self, enqueued_at: Instant) {
+        self.enqueued_at = Some(enqueued_at);
+        if let Some(headers) = &mut self.headers {
+            headers.set_enqueued_at(enqueued_at);
+        }
+    }
+
+    /// Hold the message in the executor until the delivery deadline
+    pub(crate) fn schedule(mut self, delivery: Delivery) -> Self {
+        self.delivery = Some(Box::new(delivery));
+        self
+    }
+
+    pub(crate) fn take_delivery(&mut self) -> Option<Delivery> {
+        self.delivery.take().map(|delivery| *delivery)
+    }
+
+    /// Splits off the content along with where it came from, leaving an envelope which must be
+    /// refilled before it is handled
+    pub(crate) fn into_marker(mut self) -> (Self, Contents) {
+        let contents = Contents {
+            message: std::mem::replace(&mut self.content, Box::new(())),
+            origin: self.origin.take(),
+            enqueued_at: self.enqueued_at,
+            headers: self.headers.take(),
+        };
+        self.coalesced = true;
+        (self, contents)
+    }
+
+    pub(crate) fn is_coalesced(&self) -> bool {
+        self.coalesced
+    }
+
+    pub(crate) fn refill(mut self, contents: Contents) -> Self {
+        self.content = contents.message;
+        self.origin = contents.origin;
+        self.enqueued_at = contents.enqueued_at;
+        self.headers = contents.headers;
+        self.coalesced = false;
+        self
+    }
+
+    pub(crate) fn set_counted(&mut self, counted: bool) {
+        self.counted = counted;
+    }
+
+    pub(crate) fn take_counted(&mut self) -> bool {
+        std::mem::take(&mut self.counted)
+    }
+
+    pub(crate) fn is_request(&self) -> bool {
+        self.abort.is_some()
+    }
+
+    pub(crate) fn is_scheduled(&self) -> bool {
+        self.delivery.is_some()
+    }
+
+    pub(crate) fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Recover the message, if it is of type `M`
+    #[cfg(feature = "testing")]
+    pub(crate) fn downcast<M: 'static>(self) -> Result<M, Self> {
+        if self.type_id != TypeId::of::<M>() {
+            return Err(self);
+        }
+
+        Ok(Self::unpack(self.content))
+    }
+
+    /// Recover the message, the caller must have already checked that it is of type `M`
+    pub(crate) fn into_message<M: 'static>(self) -> M {
+        Self::unpack(self.content)
+    }
+
+    /// Discard the handler, keeping only the type-erased message
+    pub(crate) fn into_content(self) -> Box<dyn Any + Send> {
+        self.content
     }
 
     pub(crate) fn unpack<M: 'static>(val: Box<dyn Any>) -> M {
@@ -54,8 +279,99 @@ impl<A> Envelope<A> {
         Box::new(fun)
     }
 
-    pub(crate) async fn resolve(self, actor: &mut A, ctx: &Context<A>) {
-        let fut = (self.mapping)(actor, self.content, ctx);
-        fut.await;
+    pub(crate) fn into_future<'a>(
+        self,
+        actor: &'a mut A,
+        ctx: &'a Context<A>,
+    ) -> Pin<Box<dyn Future<Output = ()> + 'a + Send>> {
+        (self.mapping)(actor, self.content, ctx)
+    }
+}
+
+type LocalFutType<A> = for<'a> fn(
+    &'a mut A,
+    Box<dyn Any + Send>,
+    &'a LocalContext<A>,
+) -> Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// The [`Envelope`] counterpart for actors run on a [`LocalExecutor`](crate::LocalExecutor)
+///
+/// The handler future is not required to be `Send`, however the envelope itself is, since the
+/// mapping is a plain function pointer and the content is required to be `Send`.
+pub(crate) struct LocalEnvelope<A> {
+    type_name: &'static str,
+    content: Box<dyn Any + Send>,
+    mapping: LocalFutType<A>,
+}
+
+impl<A> LocalEnvelope<A> {
+    pub(crate) fn pack<M>(message: M) -> Self
+    where
+        M: Message,
+        A: 'static + LocalHandler<M>,
+    {
+        let mapping: LocalFutType<A> = |actor, msg, ctx| {
+            let message = Envelope::<A>::unpack::<M>(msg);
+            Box::pin(actor.handle(message, ctx))
+        };
+
+        Self {
+            type_name: std::any::type_name::<M>(),
+            content: Box::new(message),
+            mapping,
+        }
+    }
+
+    pub(crate) async fn resolve(self, actor: &mut A, ctx: &LocalContext<A>) {
+        (self.mapping)(actor, self.content, ctx).await;
+    }
+
+    /// Report the message to `sink`, falling back to the global sink
+    pub(crate) fn dead_letter(
+        self,
+        target: ActorId,
+        sink: Option<DeadLetters>,
+        reason: DeadLetterReason,
+    ) {
+        let letter = DeadLetter::new(self.type_name, target, reason, Some(self.content));
+        DeadLetters::deliver(sink, letter);
+    }
+}
+
+type SyncFnType<A> = fn(&mut A, Box<dyn Any + Send>, &SyncContext<A>);
+
+/// The [`Envelope`] counterpart for actors run on a [`SyncExecutor`](crate::SyncExecutor)
+pub(crate) struct SyncEnvelope<A> {
+    type_name: &'static str,
+    content: Box<dyn Any + Send>,
+    mapping: SyncFnType<A>,
+}
+
+impl<A> SyncEnvelope<A> {
+    pub(crate) fn pack<M>(message: M) -> Self
+    where
+        M: Message,
+        A: 'static + SyncHandler<M>,
+    {
+        let mapping: SyncFnType<A> = |actor, msg, ctx| {
+            let message = Envelope::<A>::unpack::<M>(msg);
+            actor.handle(message, ctx);
+        };
+
+        Self {
+            type_name: std::any::type_name::<M>(),
+            content: Box::new(message),
+            mapping,
+        }
+    }
+
+    pub(crate) fn resolve(self, actor: &mut A, ctx: &SyncContext<A>) {
+        (self.mapping)(actor, self.content, ctx);
+    }
+
+    /// See [`LocalEnvelope::dead_letter`]
+    pub(crate) fn dead_letter(self, target: ActorId, reason: DeadLetterReason) {
+        let letter = DeadLetter::new(self.type_name, target, reason, Some(self.content));
+        DeadLetters::deliver(None, letter);
     }
 }
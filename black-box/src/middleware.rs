@@ -0,0 +1,344 @@
+use std::{any::TypeId, future::Future, pin::Pin, sync::Mutex};
+
+use crate::{batch::Batch, executor::Context, message::Envelope};
+
+/// The future returned by a [`Middleware`]
+pub type MiddlewareFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Wraps the dispatch of every message handled by an [`Executor`](crate::Executor)
+///
+/// Middleware is registered with [`Executor::add_middleware`](crate::Executor::add_middleware),
+/// and sees each message before its handler is invoked. Calling [`Next::run`] continues down the
+/// chain, and eventually into the [`Handler`](crate::Handler). Middleware can observe the dispatch
+/// by doing work around that call, delay it by awaiting beforehand, or short-circuit it by never
+/// calling it at all, in which case the message is reported as a dead letter with
+/// [`DeadLetterReason::Rejected`](crate::DeadLetterReason::Rejected).
+///
+/// Batches enabled with [`Executor::set_batch_limit`](crate::Executor::set_batch_limit) pass
+/// through the chain once as a whole, see [`Next::len`].
+///
+/// Handler timeouts and forceful shutdown apply to the whole chain, not just the handler.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Log;
+///
+/// impl<A: Send> Middleware<A> for Log {
+///     fn call<'a>(&'a self, next: Next<'a, A>) -> MiddlewareFuture<'a> {
+///         Box::pin(async move {
+///             println!("handling {}", next.type_name());
+///             next.run().await;
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware<A>: Send + Sync {
+    fn call<'a>(&'a self, next: Next<'a, A>) -> MiddlewareFuture<'a>;
+}
+
+/// The messages taken from the mailbox for a single dispatch
+pub(crate) enum Work<A> {
+    Single(Envelope<A>),
+    Batch(Vec<Envelope<A>>, Batch<A>),
+}
+
+impl<A> Work<A> {
+    fn first(&self) -> &Envelope<A> {
+        match self {
+            Work::Single(env) => env,
+            Work::Batch(envs, _) => &envs[0],
+        }
+    }
+
+    pub(crate) fn into_envelopes(self) -> Vec<Envelope<A>> {
+        match self {
+            Work::Single(env) => vec![env],
+            Work::Batch(envs, _) => envs,
+        }
+    }
+}
+
+/// Holds the work a middleware dropped without calling [`Next::run`], so the executor can report it
+pub(crate) type Rejected<A> = Mutex<Option<Work<A>>>;
+
+/// Moves the work into the rejected slot unless it is handed on
+struct Pending<'a, A> {
+    work: Option<Work<A>>,
+    rejected: &'a Rejected<A>,
+}
+
+impl<A> Pending<'_, A> {
+    fn take(mut self) -> Work<A> {
+        self.work.take().unwrap()
+    }
+}
+
+impl<A> Drop for Pending<'_, A> {
+    fn drop(&mut self) {
+        if let Some(work) = self.work.take() {
+            *self.rejected.lock().unwrap() = Some(work);
+        }
+    }
+}
+
+/// The remainder of the middleware chain for a single dispatch
+pub struct Next<'a, A> {
+    pending: Pending<'a, A>,
+    actor: &'a mut A,
+    ctx: &'a Context<A>,
+    rest: &'a [Box<dyn Middleware<A>>],
+}
+
+impl<'a, A> Next<'a, A> {
+    fn work(&self) -> &Work<A> {
+        self.pending.work.as_ref().unwrap()
+    }
+
+    /// The type name of the message being dispatched
+    pub fn type_name(&self) -> &'static str {
+        self.work().first().type_name()
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.work().first().type_id()
+    }
+
+    /// The number of messages being dispatched, more than one only for a batch
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self.work() {
+            Work::Single(_) => 1,
+            Work::Batch(envs, _) => envs.len(),
+        }
+    }
+
+    /// Read-only access to the actor the message is being dispatched to
+    pub fn actor(&self) -> &A {
+        self.actor
+    }
+
+    pub fn context(&self) -> &Context<A> {
+        self.ctx
+    }
+
+    /// Continue dispatching the message, through any remaining middleware and then the handler
+    pub fn run(self) -> MiddlewareFuture<'a> {
+        match self.rest.split_first() {
+            Some((middleware, rest)) => middleware.call(Next { rest, ..self }),
+            None => match self.pending.take() {
+                Work::Single(env) => env.into_future(self.actor, self.ctx),
+                Work::Batch(envs, batch) => (batch.handle)(self.actor, envs, self.ctx),
+            },
+        }
+    }
+}
+
+impl<A> std::fmt::Debug for Next<'_, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next")
+            .field("type_name", &self.type_name())
+            .field("remaining", &self.rest.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The middleware registered on an executor, outermost first
+pub(crate) struct Chain<A>(Vec<Box<dyn Middleware<A>>>);
+
+impl<A> Default for Chain<A> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<A> std::fmt::Debug for Chain<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chain").field("len", &self.0.len()).finish()
+    }
+}
+
+impl<A> Chain<A> {
+    pub(crate) fn push<M: Middleware<A> + 'static>(&mut self, middleware: M) {
+        self.0.push(Box::new(middleware));
+    }
+
+    /// Dispatch `work` through the chain, leaving it in `rejected` if a middleware drops it
+    pub(crate) async fn resolve(
+        &self,
+        work: Work<A>,
+        actor: &mut A,
+        ctx: &Context<A>,
+        rejected: &Rejected<A>,
+    ) {
+        let next = Next {
+            pending: Pending {
+                work: Some(work),
+                rejected,
+            },
+            actor,
+            ctx,
+            rest: &self.0,
+        };
+
+        next.run().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        error::AskError, Actor, BatchHandler, DeadLetters, Executor, Handler, Request,
+        RequestHandler,
+    };
+
+    use super::*;
+
+    struct Read;
+    struct Reset;
+
+    #[derive(Default)]
+    struct Store {
+        reads: usize,
+        resets: usize,
+    }
+
+    impl Actor for Store {}
+
+    impl Handler<Read> for Store {
+        async fn handle(&mut self, _msg: Read, _ctx: &Context<Self>) {
+            self.reads += 1;
+        }
+    }
+
+    impl BatchHandler<Read> for Store {
+        async fn handle_batch(&mut self, msgs: Vec<Read>, _ctx: &Context<Self>) {
+            self.reads += msgs.len();
+        }
+    }
+
+    impl Handler<Reset> for Store {
+        async fn handle(&mut self, _msg: Reset, _ctx: &Context<Self>) {
+            self.resets += 1;
+        }
+    }
+
+    impl Request for Reset {
+        type Response = ();
+    }
+
+    impl RequestHandler<Reset> for Store {
+        async fn respond(&mut self, _msg: Reset, _ctx: &Context<Self>) {
+            self.resets += 1;
+        }
+    }
+
+    struct Record(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl<A: Send> Middleware<A> for Record {
+        fn call<'a>(&'a self, next: Next<'a, A>) -> MiddlewareFuture<'a> {
+            Box::pin(async move {
+                let name = next.type_name().rsplit("::").next().unwrap();
+                self.1.lock().unwrap().push(format!("{} > {name}", self.0));
+                next.run().await;
+                self.1.lock().unwrap().push(format!("{} < {name}", self.0));
+            })
+        }
+    }
+
+    struct Sizes(Arc<Mutex<Vec<usize>>>);
+
+    impl<A: Send> Middleware<A> for Sizes {
+        fn call<'a>(&'a self, next: Next<'a, A>) -> MiddlewareFuture<'a> {
+            self.0.lock().unwrap().push(next.len());
+            next.run()
+        }
+    }
+
+    struct DenyResets;
+
+    impl Middleware<Store> for DenyResets {
+        fn call<'a>(&'a self, next: Next<'a, Store>) -> MiddlewareFuture<'a> {
+            Box::pin(async move {
+                if next.type_id() != TypeId::of::<Reset>() {
+                    next.run().await;
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn chain_runs_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (mut executor, addr) = Executor::new(Store::default());
+        executor.add_middleware(Record("outer", log.clone()));
+        executor.add_middleware(Record("inner", log.clone()));
+        addr.send(Read).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().reads, 1);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "outer > Read",
+                "inner > Read",
+                "inner < Read",
+                "outer < Read"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware_can_short_circuit() {
+        let (mut executor, addr) = Executor::new(Store::default());
+        executor.add_middleware(DenyResets);
+        let letters = Arc::new(Mutex::new(Vec::new()));
+        let sink = letters.clone();
+        executor.set_dead_letters(DeadLetters::new(move |letter| {
+            sink.lock()
+                .unwrap()
+                .push((letter.type_name(), letter.reason()));
+        }));
+        addr.send(Reset).await;
+        addr.send(Read).await;
+        let ask = tokio::spawn({
+            let addr = addr.clone();
+            async move { addr.ask(Reset).await }
+        });
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().resets, 0);
+        assert_eq!(executor.actor_ref().reads, 1);
+        assert_eq!(ask.await.unwrap(), Err(AskError::Rejected));
+        let name = std::any::type_name::<Reset>();
+        assert_eq!(
+            *letters.lock().unwrap(),
+            [
+                (name, crate::DeadLetterReason::Rejected),
+                (name, crate::DeadLetterReason::Rejected)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn batches_pass_through_once() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let (mut executor, addr) = Executor::new(Store::default());
+        executor.set_batch_limit::<Read>(8);
+        executor.add_middleware(Sizes(sizes.clone()));
+        for _ in 0..3 {
+            addr.send(Read).await;
+        }
+        addr.send(Reset).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().reads, 3);
+        assert_eq!(*sizes.lock().unwrap(), [3, 1]);
+    }
+}
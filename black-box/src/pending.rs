@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+
+use crate::{dead_letter::DeadLetterReason, executor::Settings, message::Envelope, Address};
+
+/// The messages an actor had yet to handle when its executor was stopped with
+/// [`Executor::into_parts`](crate::Executor::into_parts)
+///
+/// Messages are kept in the order they would have been handled. Those sent with
+/// [`Address::send_after`] or [`Address::send_at`] keep their original deadline, and any
+/// [`ScheduleHandle`](crate::ScheduleHandle) for them can still cancel them.
+pub struct Pending<A> {
+    /// In the order they would have been handled
+    pub(crate) queued: VecDeque<Envelope<A>>,
+    /// Stashed with [`Context::stash`](crate::Context::stash) and not yet unstashed
+    pub(crate) stashed: VecDeque<Envelope<A>>,
+    /// The configuration of the executor which was stopped, only set by
+    /// [`Executor::into_parts`](crate::Executor::into_parts)
+    pub(crate) settings: Option<Settings<A>>,
+}
+
+impl<A> std::fmt::Debug for Pending<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending")
+            .field("queued", &self.queued.len())
+            .field("stashed", &self.stashed.len())
+            .finish()
+    }
+}
+
+impl<A> Pending<A> {
+    /// The number of pending messages, including those stashed
+    pub fn len(&self) -> usize {
+        self.queued.len() + self.stashed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The type names of the pending messages, in the order they would have been handled,
+    /// followed by those stashed
+    pub fn type_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        let envelopes = self.queued.iter().chain(&self.stashed);
+        envelopes.map(|env| env.type_name())
+    }
+
+    /// Send every pending message to `address`, in order
+    ///
+    /// Stashed messages can only be stashed again by [`Executor::from_parts`], here they are sent
+    /// after the rest. The configuration of the stopped executor is only carried over by
+    /// [`Executor::from_parts`], the executor behind `address` keeps its own. Each message waits for capacity as with [`Address::send`], messages which
+    /// cannot be delivered are reported as a [`DeadLetter`](crate::DeadLetter).
+    ///
+    /// [`Executor::from_parts`]: crate::Executor::from_parts
+    pub async fn resend(self, address: &Address<A>) {
+        for env in self.queued.into_iter().chain(self.stashed) {
+            if let Err(env) = address.post(env).await {
+                address
+                    .mailbox()
+                    .dead_letter(env, DeadLetterReason::MailboxClosed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{timer::ManualTimer, Actor, Context, Executor, Handler};
+
+    struct Push(u32);
+    struct Park(u32);
+    struct Release;
+
+    #[derive(Default)]
+    struct Log(Vec<u32>);
+    impl Actor for Log {}
+
+    impl Handler<Push> for Log {
+        async fn handle(&mut self, msg: Push, _ctx: &Context<Self>) {
+            self.0.push(msg.0);
+        }
+    }
+
+    impl Handler<Park> for Log {
+        async fn handle(&mut self, msg: Park, ctx: &Context<Self>) {
+            ctx.stash(Push(msg.0));
+        }
+    }
+
+    impl Handler<Release> for Log {
+        async fn handle(&mut self, _msg: Release, ctx: &Context<Self>) {
+            ctx.unstash_all();
+        }
+    }
+
+    /// Lets the executor run until it is waiting again
+    async fn settle() {
+        for _ in 0..5 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_actor_with_pending_messages() {
+        let timer = ManualTimer::new();
+        let (mut executor, addr) = Executor::new(Log::default());
+        executor.set_timer(timer.clone());
+        addr.send(Push(1)).await;
+        addr.send(Park(2)).await;
+        addr.send_after(Push(4), Duration::from_millis(20)).await;
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+
+        addr.send(Push(3)).await;
+        addr.send(Release).await;
+        let (actor, pending) = executor.into_parts();
+        assert_eq!(actor.0, [1]);
+        assert_eq!(pending.len(), 4);
+        assert!(addr.is_closed());
+
+        // Held back messages keep their deadline in the new executor, which keeps the timer
+        let (mut executor, _addr) = Executor::from_parts(actor, pending);
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(executor.actor_ref().0, [1, 3, 2]);
+
+        timer.advance(Duration::from_millis(20));
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(executor.actor_ref().0, [1, 3, 2, 4]);
+    }
+
+    #[tokio::test]
+    async fn configuration_survives_the_split() {
+        let (mut executor, _addr) = Executor::new_with_capacity(Log::default(), 2);
+        executor.set_coalescing::<Push>();
+        let (actor, pending) = executor.into_parts();
+
+        let (executor, addr) = Executor::from_parts(actor, pending);
+        assert_eq!(addr.capacity(), Some(2));
+        // Coalesced into one, leaving room for a second message
+        addr.send(Push(1)).await;
+        addr.send(Push(2)).await;
+        assert_eq!(addr.len(), 1);
+        addr.send(Park(3)).await;
+        assert!(addr.is_full());
+        drop(executor);
+    }
+
+    #[tokio::test]
+    async fn resends_to_another_address() {
+        let (executor, addr) = Executor::new(Log::default());
+        addr.send(Push(1)).await;
+        addr.send(Push(2)).await;
+        let (_, pending) = executor.into_parts();
+        assert_eq!(
+            pending.type_names().collect::<Vec<_>>(),
+            [std::any::type_name::<Push>(); 2]
+        );
+
+        let (mut executor, addr) = Executor::new(Log::default());
+        pending.resend(&addr).await;
+        drop(addr);
+        assert!(executor.run().await.is_err());
+        assert_eq!(executor.actor_ref().0, [1, 2]);
+    }
+}
@@ -0,0 +1,414 @@
+//! Event-sourced actors whose state survives a restart
+//!
+//! A [`PersistentActor`] never mutates its state directly from a handler. Instead the handler
+//! calls [`PersistentActor::persist`] with an event describing the change, which is appended to
+//! the actor's [`Journal`] before being applied. When the actor is started again, typically in
+//! [`Actor::starting`], [`PersistentActor::recover`] replays the journal to rebuild the state.
+//! The actor keeps count of the events it has applied, so recovering again, for instance when the
+//! executor is run again, only applies events it has not yet seen.
+//!
+//! Events are serialized with serde as JSON.
+//!
+//! # Example
+//!
+//! ```
+//! # use black_box::{*, persistence::*};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Serialize, Deserialize)]
+//! enum LedgerEvent {
+//!     Credited(u64),
+//! }
+//!
+//! struct Credit(u64);
+//!
+//! struct Ledger {
+//!     balance: u64,
+//!     journal: InMemoryJournal,
+//!     recovered: usize,
+//! }
+//!
+//! impl Actor for Ledger {
+//!     async fn starting(&mut self, ctx: &Context<Self>) {
+//!         if self.recover().is_err() {
+//!             ctx.shutdown();
+//!         }
+//!     }
+//! }
+//!
+//! impl PersistentActor for Ledger {
+//!     type Event = LedgerEvent;
+//!
+//!     fn journal(&mut self) -> &mut dyn Journal {
+//!         &mut self.journal
+//!     }
+//!
+//!     fn recovered(&mut self) -> &mut usize {
+//!         &mut self.recovered
+//!     }
+//!
+//!     fn apply(&mut self, event: LedgerEvent) {
+//!         match event {
+//!             LedgerEvent::Credited(amount) => self.balance += amount,
+//!         }
+//!     }
+//! }
+//!
+//! impl Handler<Credit> for Ledger {
+//!     async fn handle(&mut self, msg: Credit, _ctx: &Context<Self>) {
+//!         self.persist(LedgerEvent::Credited(msg.0)).unwrap();
+//!     }
+//! }
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::Actor;
+
+/// An append-only log of serialized events
+///
+/// Each event is handed to the journal as the bytes of a single JSON value, which never contain
+/// a newline.
+pub trait Journal: Send + 'static {
+    /// Durably record the event, once this returns the event must survive a restart
+    ///
+    /// This is called from within a handler, so the actor's executor waits for it to return.
+    fn append(&mut self, event: &[u8]) -> io::Result<()>;
+
+    /// Every event recorded so far, oldest first
+    fn replay(&mut self) -> io::Result<Vec<Vec<u8>>>;
+}
+
+/// An [`Actor`] whose state is rebuilt from the events in its [`Journal`]
+pub trait PersistentActor: Actor {
+    type Event: Serialize + DeserializeOwned;
+
+    fn journal(&mut self) -> &mut dyn Journal;
+
+    /// The number of events in the journal which have been applied to the state
+    ///
+    /// This starts at zero, and is maintained by [`Self::persist`] and [`Self::recover`].
+    fn recovered(&mut self) -> &mut usize;
+
+    /// Apply the event to the actor's state
+    ///
+    /// This is invoked both for newly persisted events and while recovering, so it should not
+    /// have side effects beyond the actor's own state.
+    fn apply(&mut self, event: Self::Event);
+
+    /// Append the event to the journal, and then apply it
+    ///
+    /// The event is not applied if it could not be appended.
+    fn persist(&mut self, event: Self::Event) -> Result<(), PersistError> {
+        let bytes = serde_json::to_vec(&event)?;
+        self.journal().append(&bytes)?;
+        self.apply(event);
+        *self.recovered() += 1;
+        Ok(())
+    }
+
+    /// Replay the events in the journal which have not yet been applied, returning their number
+    ///
+    /// Should an event fail to deserialize, the events before it remain applied, and recovering
+    /// again resumes from the failed event.
+    fn recover(&mut self) -> Result<usize, PersistError> {
+        let events = self.journal().replay()?;
+        let skip = *self.recovered();
+        for bytes in events.iter().skip(skip) {
+            let event = serde_json::from_slice(bytes)?;
+            self.apply(event);
+            *self.recovered() += 1;
+        }
+
+        Ok(events.len().saturating_sub(skip))
+    }
+}
+
+/// The reason an event could not be persisted or recovered
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PersistError {
+    Io(io::Error),
+    /// An event could not be serialized or deserialized
+    Codec(serde_json::Error),
+}
+
+impl std::fmt::Display for PersistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistError::Io(err) => write!(f, "Journal failed: {err}"),
+            PersistError::Codec(err) => write!(f, "Failed to encode event: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PersistError::Io(err) => Some(err),
+            PersistError::Codec(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for PersistError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Codec(err)
+    }
+}
+
+/// A [`Journal`] held in memory
+///
+/// Clones share the same events, so a clone handed to a new actor instance recovers the state of
+/// the previous one. Useful in tests, and for state which only needs to survive an actor being
+/// restarted rather than the process.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryJournal {
+    events: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl InMemoryJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of events recorded
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Journal for InMemoryJournal {
+    fn append(&mut self, event: &[u8]) -> io::Result<()> {
+        self.events.lock().unwrap().push(event.to_vec());
+        Ok(())
+    }
+
+    fn replay(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        Ok(self.events.lock().unwrap().clone())
+    }
+}
+
+/// A [`Journal`] stored in a file, with one event per line
+///
+/// Every append is synced to disk before returning, which blocks the thread running the actor's
+/// executor until the disk has caught up. Actors persisting at a high rate are best run on their
+/// own thread, or given a [`Journal`] which hands the writes to a background thread.
+///
+/// A final line which was only partially written, because the process crashed mid-append, is
+/// discarded on replay and overwritten by the next append.
+#[derive(Debug)]
+pub struct FileJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileJournal {
+    /// Open the journal at `path`, creating the file if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads every complete line, returning the events along with the length of the file they
+    /// occupy
+    fn read(&mut self) -> io::Result<(Vec<Vec<u8>>, u64)> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut events = Vec::new();
+        let mut complete = 0;
+        loop {
+            let mut line = Vec::new();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+
+            complete += read as u64;
+            line.pop();
+            events.push(line);
+        }
+
+        Ok((events, complete))
+    }
+}
+
+impl Journal for FileJournal {
+    fn append(&mut self, event: &[u8]) -> io::Result<()> {
+        let end = self.file.seek(SeekFrom::End(0))?;
+        // Only scan for a torn line when the file does not already end cleanly
+        if end > 0 {
+            let mut last = [0];
+            self.file.seek(SeekFrom::Start(end - 1))?;
+            io::Read::read_exact(&mut self.file, &mut last)?;
+            if last[0] != b'\n' {
+                let (_, complete) = self.read()?;
+                self.file.set_len(complete)?;
+            }
+        }
+
+        self.file.seek(SeekFrom::End(0))?;
+        let mut line = Vec::with_capacity(event.len() + 1);
+        line.extend_from_slice(event);
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+
+    fn replay(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        self.read().map(|(events, _)| events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{Context, Executor, Handler};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    enum Event {
+        Credited(u64),
+        Debited(u64),
+    }
+
+    enum Entry {
+        Credit(u64),
+        Debit(u64),
+    }
+
+    struct Ledger<J> {
+        balance: u64,
+        journal: J,
+        recovered: usize,
+    }
+
+    impl<J: Journal> Actor for Ledger<J> {
+        async fn starting(&mut self, _ctx: &Context<Self>) {
+            self.recover().unwrap();
+        }
+    }
+
+    impl<J: Journal> PersistentActor for Ledger<J> {
+        type Event = Event;
+
+        fn journal(&mut self) -> &mut dyn Journal {
+            &mut self.journal
+        }
+
+        fn recovered(&mut self) -> &mut usize {
+            &mut self.recovered
+        }
+
+        fn apply(&mut self, event: Event) {
+            match event {
+                Event::Credited(amount) => self.balance += amount,
+                Event::Debited(amount) => self.balance -= amount,
+            }
+        }
+    }
+
+    impl<J: Journal> Handler<Entry> for Ledger<J> {
+        async fn handle(&mut self, msg: Entry, _ctx: &Context<Self>) {
+            let event = match msg {
+                Entry::Credit(amount) => Event::Credited(amount),
+                Entry::Debit(amount) => Event::Debited(amount),
+            };
+            self.persist(event).unwrap();
+        }
+    }
+
+    async fn run<J: Journal>(journal: J, entries: Vec<Entry>) -> u64 {
+        let (mut executor, addr) = Executor::new(Ledger {
+            balance: 0,
+            journal,
+            recovered: 0,
+        });
+        for entry in entries {
+            addr.send(entry).await;
+        }
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        executor.actor_ref().balance
+    }
+
+    #[tokio::test]
+    async fn state_is_recovered_on_restart() {
+        let journal = InMemoryJournal::new();
+        let entries = vec![Entry::Credit(50), Entry::Debit(20)];
+        assert_eq!(run(journal.clone(), entries).await, 30);
+        assert_eq!(journal.len(), 2);
+
+        assert_eq!(run(journal.clone(), vec![Entry::Credit(5)]).await, 35);
+        assert_eq!(journal.len(), 3);
+    }
+
+    #[test]
+    fn recovering_again_only_applies_new_events() {
+        let mut journal = InMemoryJournal::new();
+        journal.append(br#"{"Credited":10}"#).unwrap();
+        let mut ledger = Ledger {
+            balance: 0,
+            journal: journal.clone(),
+            recovered: 0,
+        };
+        assert_eq!(ledger.recover().unwrap(), 1);
+        ledger.persist(Event::Debited(3)).unwrap();
+
+        // Appended by another instance sharing the journal
+        journal.append(br#"{"Credited":5}"#).unwrap();
+        assert_eq!(ledger.recover().unwrap(), 1);
+        assert_eq!(ledger.recover().unwrap(), 0);
+        assert_eq!(ledger.balance, 12);
+    }
+
+    #[tokio::test]
+    async fn file_journal_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("black-box-{}.journal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let journal = FileJournal::open(&path).unwrap();
+        assert_eq!(run(journal, vec![Entry::Credit(10)]).await, 10);
+
+        // Simulate a crash part way through an append
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"Credi").unwrap();
+
+        let journal = FileJournal::open(&path).unwrap();
+        assert_eq!(run(journal, vec![Entry::Credit(1)]).await, 11);
+
+        let mut journal = FileJournal::open(&path).unwrap();
+        assert_eq!(journal.replay().unwrap().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{message::Message, Actor, Address, Executor, Handler};
+
+/// Picks which worker in a [`Pool`] receives the next message
+///
+/// Broadcasting is not a routing decision, see [`PoolAddress::broadcast`].
+pub trait Router: Send + Sync + 'static {
+    /// Returns the index of the worker, which must be less than `workers`
+    ///
+    /// `depth` returns the number of messages currently queued for the worker at the given index.
+    fn route(&self, workers: usize, depth: &dyn Fn(usize) -> usize) -> usize;
+}
+
+/// Routes each message to the next worker in turn, the default [`Router`]
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl Router for RoundRobin {
+    fn route(&self, workers: usize, _depth: &dyn Fn(usize) -> usize) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % workers
+    }
+}
+
+/// Routes each message to the worker with the fewest messages queued, preferring the earliest
+/// worker on a tie
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeastLoaded;
+
+impl Router for LeastLoaded {
+    fn route(&self, workers: usize, depth: &dyn Fn(usize) -> usize) -> usize {
+        (0..workers).min_by_key(|&i| depth(i)).unwrap_or(0)
+    }
+}
+
+/// Routes each message to a worker chosen at random
+#[derive(Debug)]
+pub struct Random {
+    state: AtomicU64,
+}
+
+impl Random {
+    /// Construct a router with a fixed seed, so the sequence of workers is reproducible
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        let seed = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        Self::with_seed(seed)
+    }
+}
+
+impl Router for Random {
+    fn route(&self, workers: usize, _depth: &dyn Fn(usize) -> usize) -> usize {
+        // SplitMix64
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z % workers as u64) as usize
+    }
+}
+
+/// A fixed number of executors for the same actor type, sharing a single [`PoolAddress`]
+///
+/// Black-box does not ship with a runtime, so the pool does not spawn its executors itself.
+/// Instead it is consumed as an iterator, and each executor handed to your runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// # use black_box::*;
+/// struct Resize(Vec<u8>);
+///
+/// struct Worker;
+/// impl Actor for Worker {}
+/// impl Handler<Resize> for Worker {
+///     async fn handle(&mut self, _msg: Resize, _ctx: &Context<Self>) {}
+/// }
+///
+/// # async fn run() {
+/// let (pool, addr) = Pool::with_router(4, LeastLoaded, |_| Worker);
+/// for mut executor in pool {
+///     tokio::spawn(async move { executor.run().await });
+/// }
+///
+/// addr.send(Resize(vec![])).await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Pool<A> {
+    executors: Vec<Executor<A>>,
+}
+
+impl<A> Pool<A> {
+    /// Construct `workers` executors, routing messages between them with [`RoundRobin`]
+    ///
+    /// `factory` is called with the index of each worker to construct its actor.
+    pub fn new<F>(workers: usize, factory: F) -> (Self, PoolAddress<A>)
+    where
+        F: FnMut(usize) -> A,
+    {
+        Self::with_router(workers, RoundRobin::default(), factory)
+    }
+
+    /// Construct `workers` executors, routing messages between them with the given [`Router`]
+    ///
+    /// # Panics
+    ///
+    /// If `workers` is zero.
+    pub fn with_router<R, F>(workers: usize, router: R, mut factory: F) -> (Self, PoolAddress<A>)
+    where
+        R: Router,
+        F: FnMut(usize) -> A,
+    {
+        assert!(workers > 0, "a pool requires at least one worker");
+
+        let (executors, addresses) = (0..workers)
+            .map(|i| Executor::new(factory(i)))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        let address = PoolAddress {
+            addresses: addresses.into(),
+            router: Arc::new(router),
+        };
+
+        (Self { executors }, address)
+    }
+
+    /// The executors in the pool, to configure them before they are run
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Executor<A>> {
+        self.executors.iter_mut()
+    }
+}
+
+impl<A> IntoIterator for Pool<A> {
+    type Item = Executor<A>;
+    type IntoIter = std::vec::IntoIter<Executor<A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.executors.into_iter()
+    }
+}
+
+/// A cloneable address which routes each message to one of the workers in a [`Pool`]
+pub struct PoolAddress<A> {
+    addresses: Arc<[Address<A>]>,
+    router: Arc<dyn Router>,
+}
+
+impl<A> Clone for PoolAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            addresses: self.addresses.clone(),
+            router: self.router.clone(),
+        }
+    }
+}
+
+impl<A> std::fmt::Debug for PoolAddress<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolAddress")
+            .field("workers", &self.addresses.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> PoolAddress<A> {
+    /// The addresses of the individual workers
+    pub fn addresses(&self) -> &[Address<A>] {
+        &self.addresses
+    }
+
+    fn route(&self) -> &Address<A> {
+        let depth = |i: usize| self.addresses[i].len();
+        let index = self.router.route(self.addresses.len(), &depth);
+        &self.addresses[index]
+    }
+}
+
+impl<A> PoolAddress<A>
+where
+    A: 'static + Actor + Send,
+{
+    /// Send the message to the worker chosen by the router
+    ///
+    /// If that worker's mailbox is full, this awaits capacity rather than rerouting.
+    pub async fn send<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        self.route().send(message).await
+    }
+
+    /// See [`Address::send_blocking`]
+    pub fn send_blocking<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        self.route().send_blocking(message)
+    }
+
+    pub fn try_send<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        self.route().try_send(message)
+    }
+
+    /// Send a copy of the message to every worker
+    pub async fn broadcast<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Message + Clone,
+    {
+        for address in self.addresses.iter() {
+            address.send(message.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::Context;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Job;
+
+    struct Worker(usize, Arc<Mutex<Vec<usize>>>);
+
+    impl Actor for Worker {}
+
+    impl Handler<Job> for Worker {
+        async fn handle(&mut self, _msg: Job, _ctx: &Context<Self>) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    fn depths(addr: &PoolAddress<Worker>) -> Vec<usize> {
+        addr.addresses().iter().map(Address::len).collect()
+    }
+
+    #[tokio::test]
+    async fn round_robin_cycles_workers() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (pool, addr) = Pool::new(3, |i| Worker(i, log.clone()));
+        for _ in 0..6 {
+            addr.send(Job).await;
+        }
+        drop(addr);
+
+        for mut executor in pool {
+            assert!(executor.run().await.is_err());
+        }
+        assert_eq!(*log.lock().unwrap(), vec![0, 0, 1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn least_loaded_fills_shortest_queue() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (_pool, addr) = Pool::with_router(3, LeastLoaded, |i| Worker(i, log.clone()));
+        addr.addresses()[0].send(Job).await;
+        addr.addresses()[0].send(Job).await;
+        addr.addresses()[2].send(Job).await;
+
+        addr.send(Job).await;
+        assert_eq!(depths(&addr), vec![2, 1, 1]);
+        addr.send(Job).await;
+        assert_eq!(depths(&addr), vec![2, 2, 1]);
+        addr.send(Job).await;
+        assert_eq!(depths(&addr), vec![2, 2, 2]);
+    }
+
+    #[test]
+    fn random_is_reproducible_from_seed() {
+        let route = |router: &Random| (0..32).map(|_| router.route(5, &|_| 0)).collect::<Vec<_>>();
+        let (first, second) = (Random::with_seed(7), Random::with_seed(7));
+        let picks = route(&first);
+        assert_eq!(picks, route(&second));
+        assert!(picks.iter().all(|&i| i < 5));
+    }
+
+    #[tokio::test]
+    async fn broadcast_reaches_every_worker() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (_pool, addr) = Pool::new(4, |i| Worker(i, log.clone()));
+        addr.broadcast(Job).await;
+        assert_eq!(depths(&addr), vec![1, 1, 1, 1]);
+    }
+}
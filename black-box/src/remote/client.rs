@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    io,
+    marker::PhantomData,
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_channel::{Sender, WeakSender};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::{
+    frame::{self, Call, Reply, Stream},
+    RemoteError, RemoteMessage,
+};
+use crate::{
+    executor::DEFAULT_CAP,
+    request::{Request, RequestHandler},
+    Handler,
+};
+
+type Pending = Mutex<Option<HashMap<u64, Sender<Result<Value, String>>>>>;
+
+#[derive(Debug)]
+struct Connection {
+    next_id: AtomicU64,
+    /// Encoded frames waiting for the writer thread
+    outgoing: Sender<Vec<u8>>,
+    /// Asks awaiting a reply, `None` once the connection has been lost
+    pending: Arc<Pending>,
+}
+
+/// A cloneable proxy which forwards messages to an actor served by a
+/// [`RemoteNode`](super::RemoteNode)
+///
+/// The connection is closed once every clone of the address has been dropped.
+pub struct RemoteAddress<A> {
+    connection: Arc<Connection>,
+    actor: PhantomData<fn() -> A>,
+}
+
+impl<A> Clone for RemoteAddress<A> {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            actor: PhantomData,
+        }
+    }
+}
+
+impl<A> std::fmt::Debug for RemoteAddress<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteAddress")
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+impl<A> RemoteAddress<A> {
+    /// Connect to a node served with [`RemoteNode::serve_tcp`](super::RemoteNode::serve_tcp)
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Self::from_stream(stream)
+    }
+
+    /// Connect to a node served with [`RemoteNode::serve_unix`](super::RemoteNode::serve_unix)
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        Self::from_stream(std::os::unix::net::UnixStream::connect(path)?)
+    }
+
+    fn from_stream<S: Stream>(stream: S) -> io::Result<Self> {
+        let (outgoing, frames) = async_channel::bounded(DEFAULT_CAP);
+        let pending = Arc::new(Mutex::new(Some(HashMap::new())));
+
+        let reader = stream.try_clone()?;
+        let reader_pending = pending.clone();
+        let reader_outgoing = outgoing.downgrade();
+        std::thread::Builder::new()
+            .name("black-box-remote-writer".into())
+            .spawn(move || frame::write(stream, frames))?;
+        std::thread::Builder::new()
+            .name("black-box-remote-reader".into())
+            .spawn(move || read(reader, &reader_pending, reader_outgoing))?;
+
+        let connection = Connection {
+            next_id: AtomicU64::new(0),
+            outgoing,
+            pending,
+        };
+
+        Ok(Self {
+            connection: Arc::new(connection),
+            actor: PhantomData,
+        })
+    }
+
+    async fn call<M: RemoteMessage>(
+        &self,
+        id: u64,
+        message: M,
+        ask: bool,
+    ) -> Result<(), RemoteError> {
+        let call = Call {
+            id,
+            name: M::NAME.into(),
+            ask,
+            payload: serde_json::to_value(message)?,
+        };
+
+        self.connection
+            .outgoing
+            .send(frame::encode(&call)?)
+            .await
+            .map_err(|_| RemoteError::Disconnected)
+    }
+
+    /// Send the message to the remote actor
+    ///
+    /// Resolves once the message has been handed to the connection, not once it has been handled.
+    /// A message the node cannot deliver, for instance because it is not registered there, is
+    /// discarded without an error.
+    pub async fn send<M>(&self, message: M) -> Result<(), RemoteError>
+    where
+        A: Handler<M>,
+        M: RemoteMessage,
+    {
+        self.call(0, message, false).await
+    }
+
+    /// Send the request to the remote actor, and await its response
+    pub async fn ask<M>(&self, message: M) -> Result<M::Response, RemoteError>
+    where
+        A: RequestHandler<M>,
+        M: RemoteMessage + Request,
+        M::Response: DeserializeOwned,
+    {
+        let id = self.connection.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply, response) = async_channel::bounded(1);
+        match self.connection.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, reply),
+            None => return Err(RemoteError::Disconnected),
+        };
+
+        if let Err(err) = self.call(id, message, true).await {
+            if let Some(pending) = self.connection.pending.lock().unwrap().as_mut() {
+                pending.remove(&id);
+            }
+            return Err(err);
+        }
+
+        let value = response
+            .recv()
+            .await
+            .map_err(|_| RemoteError::Disconnected)?
+            .map_err(RemoteError::Node)?;
+        serde_json::from_value(value).map_err(Into::into)
+    }
+}
+
+/// Completes asks as their replies arrive, failing any which are outstanding once the connection
+/// is lost
+fn read<S: Stream>(mut stream: S, pending: &Pending, outgoing: WeakSender<Vec<u8>>) {
+    while let Ok(Some(reply)) = frame::read::<Reply>(&mut stream) {
+        let reply_to = pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|pending| pending.remove(&reply.id));
+        if let Some(reply_to) = reply_to {
+            let _ = reply_to.try_send(reply.result);
+        }
+    }
+
+    // Dropping the senders wakes the outstanding asks
+    pending.lock().unwrap().take();
+    if let Some(outgoing) = outgoing.upgrade() {
+        outgoing.close();
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Frames larger than this are treated as a corrupt connection
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// A message sent from a [`RemoteAddress`](super::RemoteAddress) to a node
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Call {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    /// Whether the caller is awaiting a [`Reply`]
+    pub(crate) ask: bool,
+    pub(crate) payload: Value,
+}
+
+/// The node's answer to a [`Call`] which was asked
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Reply {
+    pub(crate) id: u64,
+    pub(crate) result: Result<Value, String>,
+}
+
+/// Encode a frame, a big-endian `u32` length followed by the JSON body
+///
+/// A frame longer than the peer would read is rejected here, rather than having the peer drop
+/// the whole connection.
+pub(crate) fn encode<T: Serialize>(frame: &T) -> serde_json::Result<Vec<u8>> {
+    let mut bytes = vec![0; 4];
+    serde_json::to_writer(&mut bytes, frame)?;
+    if bytes.len() - 4 > MAX_FRAME {
+        return Err(serde::ser::Error::custom("frame exceeds maximum length"));
+    }
+    let len = (bytes.len() - 4) as u32;
+    bytes[..4].copy_from_slice(&len.to_be_bytes());
+    Ok(bytes)
+}
+
+/// Read the next frame, returning `None` if the connection was closed between frames
+pub(crate) fn read<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<Option<T>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame exceeds maximum length",
+        ));
+    }
+
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(Into::into)
+}
+
+/// Writes frames until every sender has been dropped, then closes the connection
+pub(crate) fn write<S: Stream>(mut stream: S, frames: async_channel::Receiver<Vec<u8>>) {
+    while let Ok(frame) = frames.recv_blocking() {
+        if stream.write_all(&frame).is_err() {
+            break;
+        }
+    }
+
+    let _ = stream.shutdown();
+}
+
+/// A connected socket which can be split between a reading and a writing thread
+pub(crate) trait Stream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        std::os::unix::net::UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
@@ -0,0 +1,358 @@
+//! Actors reachable over TCP or Unix domain sockets
+//!
+//! A [`RemoteNode`] serves an actor's [`Address`](crate::Address) on a socket, and a
+//! [`RemoteAddress`] in another process forwards messages to it. Messages cross the connection
+//! serialized with serde, and are identified by the name given in their [`RemoteMessage`]
+//! implementation, so both processes must agree on the name and the encoding of each message.
+//! The actor's [`Handler`](crate::Handler) and [`RequestHandler`](crate::RequestHandler)
+//! implementations are shared by both sides.
+//!
+//! Black-box does not ship with a runtime, so the connection is driven by plain threads doing
+//! blocking IO. Sends and asks from async code are handed to those threads over a channel, and
+//! never block the calling task.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use black_box::{*, remote::*};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Serialize, Deserialize)]
+//! struct Greet(String);
+//!
+//! impl RemoteMessage for Greet {
+//!     const NAME: &'static str = "greet";
+//! }
+//!
+//! struct Greeter;
+//! impl Actor for Greeter {}
+//! impl Handler<Greet> for Greeter {
+//!     async fn handle(&mut self, msg: Greet, _ctx: &Context<Self>) {
+//!         println!("hello {}", msg.0);
+//!     }
+//! }
+//!
+//! # async fn run() -> Result<(), RemoteError> {
+//! // On the serving process
+//! let (mut executor, addr) = Executor::new(Greeter);
+//! tokio::spawn(async move { executor.run().await });
+//!
+//! let mut node = RemoteNode::new(addr);
+//! node.register::<Greet>();
+//! let listener = std::net::TcpListener::bind("127.0.0.1:7000")?;
+//! std::thread::spawn(move || node.serve_tcp(listener));
+//!
+//! // On the calling process
+//! let remote = RemoteAddress::<Greeter>::connect_tcp("127.0.0.1:7000")?;
+//! remote.send(Greet("world".into())).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod frame;
+mod node;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::message::Message;
+
+pub use self::{client::RemoteAddress, node::RemoteNode};
+
+/// A message which can be sent to a remote actor
+///
+/// `NAME` identifies the message on the wire, and must be unique amongst the messages registered
+/// with a [`RemoteNode`].
+pub trait RemoteMessage: Message + Serialize + DeserializeOwned {
+    const NAME: &'static str;
+}
+
+/// The reason a message could not be delivered to, or answered by, a remote actor
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RemoteError {
+    Io(std::io::Error),
+    /// A message or response could not be serialized or deserialized
+    Codec(serde_json::Error),
+    /// The connection to the node has been closed
+    Disconnected,
+    /// The node failed to handle the message, for instance because it is not registered
+    Node(String),
+}
+
+impl std::fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteError::Io(err) => write!(f, "Remote connection failed: {err}"),
+            RemoteError::Codec(err) => write!(f, "Failed to encode remote message: {err}"),
+            RemoteError::Disconnected => f.write_str("Remote connection closed"),
+            RemoteError::Node(err) => write!(f, "Remote node failed to handle message: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RemoteError::Io(err) => Some(err),
+            RemoteError::Codec(err) => Some(err),
+            RemoteError::Disconnected | RemoteError::Node(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RemoteError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for RemoteError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Codec(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use serde::Deserialize;
+
+    use crate::{Actor, Context, Executor, Handler, Request, RequestHandler};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Add(i64);
+
+    impl RemoteMessage for Add {
+        const NAME: &'static str = "add";
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Total;
+
+    impl RemoteMessage for Total {
+        const NAME: &'static str = "total";
+    }
+
+    impl Request for Total {
+        type Response = i64;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Reset;
+
+    impl RemoteMessage for Reset {
+        const NAME: &'static str = "reset";
+    }
+
+    impl Request for Reset {
+        type Response = ();
+    }
+
+    struct Counter(i64);
+
+    impl Actor for Counter {}
+
+    impl Handler<Add> for Counter {
+        async fn handle(&mut self, msg: Add, _ctx: &Context<Self>) {
+            self.0 += msg.0;
+        }
+    }
+
+    impl RequestHandler<Total> for Counter {
+        async fn respond(&mut self, _msg: Total, _ctx: &Context<Self>) -> i64 {
+            self.0
+        }
+    }
+
+    impl RequestHandler<Reset> for Counter {
+        async fn respond(&mut self, _msg: Reset, _ctx: &Context<Self>) {
+            self.0 = 0;
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Hold;
+
+    impl RemoteMessage for Hold {
+        const NAME: &'static str = "hold";
+    }
+
+    impl Request for Hold {
+        type Response = ();
+    }
+
+    /// Answers a [`Hold`] only once the gate is opened
+    struct Gated {
+        held: Arc<AtomicBool>,
+        gate: async_channel::Receiver<()>,
+    }
+
+    impl Actor for Gated {}
+
+    impl Handler<Add> for Gated {
+        async fn handle(&mut self, _msg: Add, _ctx: &Context<Self>) {}
+    }
+
+    impl RequestHandler<Hold> for Gated {
+        async fn respond(&mut self, _msg: Hold, _ctx: &Context<Self>) {
+            self.held.store(true, Ordering::Release);
+            let _ = self.gate.recv().await;
+        }
+    }
+
+    /// Polls `condition` until it holds, failing the test after a generous deadline
+    async fn eventually(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition not reached");
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    fn node() -> RemoteNode<Counter> {
+        let (mut executor, addr) = Executor::new(Counter(0));
+        tokio::spawn(async move { executor.run().await });
+
+        let mut node = RemoteNode::new(addr);
+        node.register::<Add>();
+        node.register_request::<Total>();
+        node
+    }
+
+    #[tokio::test]
+    async fn send_and_ask_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        let node = node();
+        std::thread::spawn(move || node.serve_tcp(listener));
+
+        let remote = RemoteAddress::<Counter>::connect_tcp(local).unwrap();
+        remote.send(Add(2)).await.unwrap();
+        remote.send(Add(40)).await.unwrap();
+        assert_eq!(remote.ask(Total).await.unwrap(), 42);
+
+        let unregistered = remote.ask(Reset).await;
+        assert!(matches!(unregistered, Err(RemoteError::Node(_))));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ask_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("black-box-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let node = node();
+        std::thread::spawn(move || node.serve_unix(listener));
+
+        let remote = RemoteAddress::<Counter>::connect_unix(&path).unwrap();
+        remote.send(Add(7)).await.unwrap();
+        assert_eq!(remote.ask(Total).await.unwrap(), 7);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_messages_fail_without_dropping_the_connection() {
+        #[derive(Serialize, Deserialize)]
+        struct Blob(String);
+
+        impl RemoteMessage for Blob {
+            const NAME: &'static str = "blob";
+        }
+
+        impl Handler<Blob> for Counter {
+            async fn handle(&mut self, _msg: Blob, _ctx: &Context<Self>) {}
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        let node = node();
+        std::thread::spawn(move || node.serve_tcp(listener));
+
+        let remote = RemoteAddress::<Counter>::connect_tcp(local).unwrap();
+        let blob = Blob("x".repeat(17 * 1024 * 1024));
+        assert!(matches!(
+            remote.send(blob).await,
+            Err(RemoteError::Codec(_))
+        ));
+        assert_eq!(remote.ask(Total).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn lost_connection_fails_asks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            // Accept a single connection and hang up without answering
+            let (stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+            drop(stream);
+        });
+
+        let remote = RemoteAddress::<Counter>::connect_tcp(local).unwrap();
+        assert!(matches!(
+            remote.ask(Total).await,
+            Err(RemoteError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pending_asks_do_not_hold_up_later_calls() {
+        let held = Arc::new(AtomicBool::new(false));
+        let (open, gate) = async_channel::bounded(1);
+        let actor = Gated {
+            held: held.clone(),
+            gate,
+        };
+        let (mut executor, addr) = Executor::new(actor);
+        let local = addr.clone();
+        tokio::spawn(async move { executor.run().await });
+        let mut node = RemoteNode::new(addr);
+        node.register::<Add>();
+        node.register_request::<Hold>();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+        std::thread::spawn(move || node.serve_tcp(listener));
+
+        let remote = RemoteAddress::<Gated>::connect_tcp(socket).unwrap();
+        let asked = tokio::spawn({
+            let remote = remote.clone();
+            async move { remote.ask(Hold).await }
+        });
+        eventually(|| held.load(Ordering::Acquire)).await;
+
+        // Enqueued while the ask before it on the same connection is still unanswered
+        remote.send(Add(1)).await.unwrap();
+        eventually(|| local.len() == 1).await;
+        open.send(()).await.unwrap();
+        assert!(asked.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn connections_beyond_the_limit_wait() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        let mut node = node();
+        node.set_max_connections(1);
+        std::thread::spawn(move || node.serve_tcp(listener));
+
+        let first = RemoteAddress::<Counter>::connect_tcp(local).unwrap();
+        assert_eq!(first.ask(Total).await.unwrap(), 0);
+        let second = RemoteAddress::<Counter>::connect_tcp(local).unwrap();
+        let asked = tokio::spawn(async move { second.ask(Total).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!asked.is_finished());
+
+        drop(first);
+        assert_eq!(asked.await.unwrap().unwrap(), 0);
+    }
+}
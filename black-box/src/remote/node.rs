@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::TcpListener,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use async_channel::Sender;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{
+    frame::{self, Call, Reply, Stream},
+    RemoteMessage,
+};
+use crate::{
+    executor::DEFAULT_CAP,
+    request::{Request, RequestHandler},
+    stage::StageHandle,
+    Actor, Address, Handler, Stage,
+};
+
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+type Response = Pin<Box<dyn Future<Output = Result<Value, String>> + Send>>;
+
+/// Resolves once the message has been enqueued, to the pending response for requests
+type Dispatched = Pin<Box<dyn Future<Output = Result<Option<Response>, String>> + Send>>;
+
+/// Decodes a payload and forwards it to the actor
+type Dispatch<A> = fn(Address<A>, Value) -> Dispatched;
+
+fn tell<A, M>(address: Address<A>, payload: Value) -> Dispatched
+where
+    A: 'static + Handler<M> + Send,
+    M: RemoteMessage,
+{
+    Box::pin(async move {
+        let message: M = serde_json::from_value(payload).map_err(|err| err.to_string())?;
+        address.send(message).await;
+        Ok(None)
+    })
+}
+
+fn ask<A, M>(address: Address<A>, payload: Value) -> Dispatched
+where
+    A: 'static + RequestHandler<M> + Send,
+    M: RemoteMessage + Request,
+    M::Response: Serialize,
+{
+    Box::pin(async move {
+        let message: M = serde_json::from_value(payload).map_err(|err| err.to_string())?;
+        let answer = address
+            .request(message)
+            .await
+            .map_err(|err| err.to_string())?;
+        let response: Response = Box::pin(async move {
+            let response = answer.recv().await.map_err(|err| err.to_string())?;
+            serde_json::to_value(response).map_err(|err| err.to_string())
+        });
+        Ok(Some(response))
+    })
+}
+
+/// Counts the connections being served, so accepting can wait for one to close
+#[derive(Default)]
+struct Connections {
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl Connections {
+    fn acquire(self: &Arc<Self>, max: usize) -> Permit {
+        let mut open = self.open.lock().unwrap();
+        while *open >= max {
+            open = self.closed.wait(open).unwrap();
+        }
+        *open += 1;
+        Permit(self.clone())
+    }
+}
+
+/// Held for as long as a connection is served
+struct Permit(Arc<Connections>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.0.open.lock().unwrap() -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
+/// Serves an actor's [`Address`] to [`RemoteAddress`](super::RemoteAddress)es in other processes
+///
+/// Only messages which have been registered are accepted. An ask for anything else, or whose
+/// payload cannot be decoded, is answered with [`RemoteError::Node`](super::RemoteError::Node).
+/// Messages sent with [`RemoteAddress::send`](super::RemoteAddress::send) get no answer, so any
+/// which cannot be delivered are discarded. Each connection is read on its own thread,
+/// which enqueues its messages in the order they were sent. Responses are awaited on a single
+/// thread shared by every connection, so a slow request does not hold up the calls behind it, and
+/// replies are sent as they become ready.
+///
+/// At most 64 connections are served at once by default, see [`Self::set_max_connections`].
+pub struct RemoteNode<A> {
+    address: Address<A>,
+    dispatch: HashMap<&'static str, Dispatch<A>>,
+    max_connections: usize,
+}
+
+impl<A> std::fmt::Debug for RemoteNode<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteNode")
+            .field("messages", &self.dispatch.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> RemoteNode<A>
+where
+    A: 'static + Actor + Send,
+{
+    pub fn new(address: Address<A>) -> Self {
+        Self {
+            address,
+            dispatch: HashMap::new(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+
+    /// Limit the number of connections served at once
+    ///
+    /// Once the limit is reached, further connections wait to be accepted until another closes.
+    pub fn set_max_connections(&mut self, max: usize) {
+        self.max_connections = max.max(1);
+    }
+
+    /// Accept messages of type `M`, which are dispatched to the actor's [`Handler`]
+    pub fn register<M>(&mut self)
+    where
+        A: Handler<M>,
+        M: RemoteMessage,
+    {
+        self.dispatch.insert(M::NAME, tell::<A, M>);
+    }
+
+    /// Accept requests of type `M`, which are dispatched to the actor's [`RequestHandler`] and
+    /// answered with its response
+    pub fn register_request<M>(&mut self)
+    where
+        A: RequestHandler<M>,
+        M: RemoteMessage + Request,
+        M::Response: Serialize,
+    {
+        self.dispatch.insert(M::NAME, ask::<A, M>);
+    }
+
+    /// Accept connections from the listener, blocking the current thread until accepting fails
+    pub fn serve_tcp(self, listener: TcpListener) -> io::Result<()> {
+        self.serve(|| listener.accept().map(|(stream, _)| stream))
+    }
+
+    /// Accept connections from the listener, blocking the current thread until accepting fails
+    #[cfg(unix)]
+    pub fn serve_unix(self, listener: std::os::unix::net::UnixListener) -> io::Result<()> {
+        self.serve(|| listener.accept().map(|(stream, _)| stream))
+    }
+
+    fn serve<S: Stream>(self, mut accept: impl FnMut() -> io::Result<S>) -> io::Result<()> {
+        // The stage runs until the handle is dropped, and any outstanding responses are sent
+        let mut stage = Stage::new();
+        let responses = stage.handle();
+        std::thread::Builder::new()
+            .name("black-box-remote-responses".into())
+            .spawn(move || crate::futures::block_on(stage.run()))?;
+
+        let node = Arc::new(self);
+        let connections = Arc::new(Connections::default());
+        loop {
+            let permit = connections.acquire(node.max_connections);
+            let stream = accept()?;
+            node.clone().spawn(stream, responses.clone(), permit)?;
+        }
+    }
+
+    fn spawn<S: Stream>(
+        self: Arc<Self>,
+        stream: S,
+        responses: StageHandle,
+        permit: Permit,
+    ) -> io::Result<()> {
+        let (outgoing, frames) = async_channel::bounded(DEFAULT_CAP);
+        let writer = stream.try_clone()?;
+        std::thread::Builder::new()
+            .name("black-box-remote-writer".into())
+            .spawn(move || frame::write(writer, frames))?;
+        std::thread::Builder::new()
+            .name("black-box-remote".into())
+            .spawn(move || {
+                let _ = self.connection(stream, &responses, outgoing);
+                drop(permit);
+            })
+            .map(drop)
+    }
+
+    fn connection<S: Stream>(
+        &self,
+        mut stream: S,
+        responses: &StageHandle,
+        outgoing: Sender<Vec<u8>>,
+    ) -> io::Result<()> {
+        while let Some(call) = frame::read::<Call>(&mut stream)? {
+            let enqueued = match self.dispatch.get(call.name.as_str()) {
+                Some(dispatch) => {
+                    crate::futures::block_on(dispatch(self.address.clone(), call.payload))
+                }
+                None => Err(format!("no message registered as {}", call.name)),
+            };
+
+            // Only an ask has anyone waiting to be told it failed
+            if !call.ask {
+                continue;
+            }
+
+            // Only the response is awaited off this thread, so calls are still enqueued in order
+            let (id, name, outgoing) = (call.id, call.name, outgoing.clone());
+            responses.spawn_job(Box::pin(async move {
+                let result = match enqueued {
+                    Ok(Some(response)) => response.await,
+                    Ok(None) => Err(format!("{name} is not a request")),
+                    Err(err) => Err(err),
+                };
+                // A response too large to send still fails the ask, rather than leaving it waiting
+                let reply = frame::encode(&Reply { id, result }).or_else(|err| {
+                    let result = Err(err.to_string());
+                    frame::encode(&Reply { id, result })
+                });
+                if let Ok(reply) = reply {
+                    let _ = outgoing.send(reply).await;
+                }
+            }));
+        }
+
+        Ok(())
+    }
+}
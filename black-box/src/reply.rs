@@ -0,0 +1,223 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    error::ReplyError, message::Message, scope::Current, Actor, ActorId, Address, Handler,
+    WeakAddress,
+};
+
+type ReplyFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type ReplyFn<A> = fn(Address<A>, Box<dyn Any + Send>) -> ReplyFuture;
+
+/// An actor which can be replied to, without knowing its type
+trait Recipient: Send + Sync {
+    fn reply(
+        &self,
+        type_id: TypeId,
+        message: Box<dyn Any + Send>,
+    ) -> Result<ReplyFuture, ReplyError>;
+}
+
+/// The reply types an actor accepts, registered with
+/// [`Executor::accept_reply`](crate::Executor::accept_reply)
+pub(crate) struct Replies<A> {
+    address: WeakAddress<A>,
+    accepted: RwLock<HashMap<TypeId, ReplyFn<A>>>,
+}
+
+impl<A> std::fmt::Debug for Replies<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Replies")
+            .field("accepted", &self.accepted.read().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> Replies<A> {
+    pub(crate) fn new(address: WeakAddress<A>) -> Self {
+        Self {
+            address,
+            accepted: Default::default(),
+        }
+    }
+
+    /// The same accepted replies, delivered to `address` instead
+    pub(crate) fn rebind(&self, address: WeakAddress<A>) -> Self {
+        Self {
+            address,
+            accepted: RwLock::new(self.accepted.read().unwrap().clone()),
+        }
+    }
+}
+
+impl<A> Replies<A>
+where
+    A: 'static + Actor + Send,
+{
+    pub(crate) fn accept<R>(&self)
+    where
+        A: Handler<R>,
+        R: Message,
+    {
+        let reply: ReplyFn<A> = |address, message| {
+            let message = *message.downcast::<R>().unwrap();
+            Box::pin(async move { address.send(message).await })
+        };
+        self.accepted
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<R>(), reply);
+    }
+}
+
+impl<A> Recipient for Replies<A>
+where
+    A: 'static,
+{
+    fn reply(
+        &self,
+        type_id: TypeId,
+        message: Box<dyn Any + Send>,
+    ) -> Result<ReplyFuture, ReplyError> {
+        let reply = *self
+            .accepted
+            .read()
+            .unwrap()
+            .get(&type_id)
+            .ok_or(ReplyError::NotAccepted)?;
+        let address = self.address.upgrade().ok_or(ReplyError::MailboxClosed)?;
+        Ok(reply(address, message))
+    }
+}
+
+/// The actor a message was sent from, carried by its envelope
+#[derive(Clone)]
+pub(crate) struct Origin(Arc<Inner>);
+
+struct Inner {
+    id: ActorId,
+    /// Only set once the actor accepts a reply
+    recipient: Option<Arc<dyn Recipient>>,
+}
+
+impl std::fmt::Debug for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Origin").field(&self.id()).finish()
+    }
+}
+
+impl Origin {
+    pub(crate) fn new(id: ActorId) -> Self {
+        Self(Arc::new(Inner {
+            id,
+            recipient: None,
+        }))
+    }
+
+    /// Route replies to the actor through `replies`
+    pub(crate) fn set_replies<A: 'static>(&mut self, replies: Arc<Replies<A>>) {
+        let id = self.id();
+        self.0 = Arc::new(Inner {
+            id,
+            recipient: Some(replies),
+        });
+    }
+
+    /// The actor whose handler is sending, if any
+    pub(crate) fn current() -> Option<Self> {
+        Current::origin()
+    }
+
+    pub(crate) fn id(&self) -> ActorId {
+        self.0.id
+    }
+
+    pub(crate) async fn reply<R: Message>(&self, message: R) -> Result<(), ReplyError> {
+        let recipient = self.0.recipient.as_ref().ok_or(ReplyError::NotAccepted)?;
+        recipient.reply(TypeId::of::<R>(), Box::new(message))?.await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{Context, Executor};
+
+    use super::*;
+
+    struct Start;
+    struct Ping;
+    struct Pong;
+
+    struct Server(Arc<Mutex<Vec<Result<(), ReplyError>>>>);
+    impl Actor for Server {}
+    impl Handler<Ping> for Server {
+        async fn handle(&mut self, _msg: Ping, ctx: &Context<Self>) {
+            let result = ctx.reply(Pong).await;
+            self.0.lock().unwrap().push(result);
+        }
+    }
+
+    struct Client {
+        server: Address<Server>,
+        pongs: usize,
+    }
+    impl Actor for Client {}
+    impl Handler<Start> for Client {
+        async fn handle(&mut self, _msg: Start, _ctx: &Context<Self>) {
+            self.server.send(Ping).await;
+        }
+    }
+    impl Handler<Pong> for Client {
+        async fn handle(&mut self, _msg: Pong, ctx: &Context<Self>) {
+            self.pongs += 1;
+            ctx.shutdown();
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_reach_the_sender() {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let (mut server, server_addr) = Executor::new(Server(results.clone()));
+        let (mut client, client_addr) = Executor::new(Client {
+            server: server_addr.clone(),
+            pongs: 0,
+        });
+        client.accept_reply::<Pong>();
+        tokio::spawn(async move { server.run().await });
+
+        server_addr.send(Ping).await;
+        client_addr.send(Start).await;
+        client.run().await.unwrap();
+
+        assert_eq!(client.actor_ref().pongs, 1);
+        assert_eq!(
+            *results.lock().unwrap(),
+            [Err(ReplyError::NoSender), Ok(())]
+        );
+    }
+
+    #[tokio::test]
+    async fn replies_must_be_accepted() {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let (mut server, server_addr) = Executor::new(Server(results.clone()));
+        let (mut client, client_addr) = Executor::new(Client {
+            server: server_addr,
+            pongs: 0,
+        });
+        client_addr.send(Start).await;
+        drop(client_addr);
+        client.run().await.unwrap_err();
+        drop(client);
+
+        server.run().await.unwrap_err();
+        assert_eq!(*results.lock().unwrap(), [Err(ReplyError::NotAccepted)]);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use crate::{error::AskError, executor::Context, message::Message, Actor};
+
+/// A message which is answered with a response, see [`Address::ask`](crate::Address::ask)
+pub trait Request: Message {
+    type Response: Send + 'static;
+}
+
+/// The implementation for how an actor answers a particular [`Request`]
+///
+/// This is the request-response counterpart of [`Handler`](crate::Handler), an actor can
+/// implement both for the same message type, in which case [`Address::send`](crate::Address::send)
+/// invokes the [`Handler`](crate::Handler) and [`Address::ask`](crate::Address::ask) invokes the
+/// [`RequestHandler`].
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Balance;
+///
+/// impl Request for Balance {
+///     type Response = u64;
+/// }
+///
+/// struct Account(u64);
+///
+/// impl Actor for Account {}
+///
+/// impl RequestHandler<Balance> for Account {
+///     async fn respond(&mut self, _msg: Balance, _ctx: &Context<Self>) -> u64 {
+///         self.0
+///     }
+/// }
+/// ```
+pub trait RequestHandler<M>
+where
+    Self: Actor,
+    M: Request,
+{
+    /// Asynchronously answer the request, with mutable access to self
+    fn respond(&mut self, msg: M, ctx: &Context<Self>) -> impl Future<Output = M::Response> + Send;
+}
+
+/// Sends the response to a request back to the asking address
+///
+/// Dropped without a response, the asker sees the error set with [`Abort::set`], which may still
+/// happen after the drop, for instance once the executor finds the handler panicked. Otherwise the
+/// asker sees [`AskError::HandlerDropped`] once every [`Abort`] handle is gone.
+pub(crate) struct Responder<R: Send + 'static> {
+    reply: Option<async_channel::Sender<Result<R, AskError>>>,
+    abort: Abort,
+}
+
+impl<R: Send + 'static> Responder<R> {
+    /// A responder along with the [`Answer`] its response arrives on
+    pub(crate) fn pair() -> (Self, Answer<R>) {
+        let (reply, response) = async_channel::bounded(1);
+        let responder = Self {
+            reply: Some(reply),
+            abort: Abort::default(),
+        };
+        (responder, Answer(response))
+    }
+
+    /// A handle for the executor to explain why the request goes unanswered
+    pub(crate) fn abort(&self) -> Abort {
+        self.abort.clone()
+    }
+
+    pub(crate) fn send(mut self, response: R) {
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.try_send(Ok(response));
+        }
+    }
+}
+
+impl<R: Send + 'static> Drop for Responder<R> {
+    fn drop(&mut self) {
+        if let Some(reply) = self.reply.take() {
+            self.abort.park(Box::new(move |error| {
+                let _ = reply.try_send(Err(error));
+            }));
+        }
+    }
+}
+
+/// The receiving end of a [`Responder`]
+pub(crate) struct Answer<R>(async_channel::Receiver<Result<R, AskError>>);
+
+impl<R> Answer<R> {
+    pub(crate) async fn recv(&self) -> Result<R, AskError> {
+        self.0.recv().await.unwrap_or(Err(AskError::HandlerDropped))
+    }
+}
+
+type Fail = Box<dyn FnOnce(AskError) + Send>;
+
+#[derive(Default)]
+struct Aborted {
+    error: Option<AskError>,
+    /// The reply channel of a responder dropped before any error was set, kept open so one set
+    /// afterwards still reaches the asker
+    parked: Option<Fail>,
+}
+
+/// The error a [`Responder`] reports if it is dropped without a response
+#[derive(Clone, Default)]
+pub(crate) struct Abort(Arc<Mutex<Aborted>>);
+
+impl std::fmt::Debug for Abort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = self.0.lock().unwrap().error.clone();
+        f.debug_tuple("Abort").field(&error).finish()
+    }
+}
+
+impl Abort {
+    /// Only the first error set is kept
+    pub(crate) fn set(&self, error: AskError) {
+        let parked = {
+            let mut aborted = self.0.lock().unwrap();
+            if aborted.error.is_some() {
+                return;
+            }
+            aborted.error = Some(error.clone());
+            aborted.parked.take()
+        };
+        if let Some(fail) = parked {
+            fail(error);
+        }
+    }
+
+    /// Report the error if one was already set, otherwise hold on to `fail` until one is
+    fn park(&self, fail: Fail) {
+        let error = {
+            let mut aborted = self.0.lock().unwrap();
+            match &aborted.error {
+                Some(error) => error.clone(),
+                None => {
+                    aborted.parked = Some(fail);
+                    return;
+                }
+            }
+        };
+        fail(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::Executor;
+
+    use super::*;
+
+    struct Deposit(u64);
+
+    impl Request for Deposit {
+        type Response = u64;
+    }
+
+    struct Audit;
+
+    impl Request for Audit {
+        type Response = ();
+    }
+
+    struct Account(u64);
+
+    impl Actor for Account {}
+
+    impl RequestHandler<Audit> for Account {
+        async fn respond(&mut self, _msg: Audit, _ctx: &Context<Self>) {
+            panic!("audit failed");
+        }
+    }
+
+    impl RequestHandler<Deposit> for Account {
+        async fn respond(&mut self, msg: Deposit, ctx: &Context<Self>) -> u64 {
+            self.0 += msg.0;
+            if self.0 > 100 {
+                ctx.shutdown();
+            }
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn ask_returns_response() {
+        let (mut executor, addr) = Executor::new(Account(0));
+        let task = tokio::spawn(async move { executor.run().await });
+
+        assert_eq!(addr.ask(Deposit(10)).await, Ok(10));
+        assert_eq!(addr.ask(Deposit(95)).await, Ok(105));
+        assert!(task.await.unwrap().is_ok());
+        assert_eq!(addr.ask(Deposit(1)).await, Err(AskError::MailboxClosed));
+    }
+
+    #[tokio::test]
+    async fn ask_timeout_reports_why_there_was_no_response() {
+        let (mut executor, addr) = Executor::new_with_capacity(Account(0), 1);
+        let timeout = Duration::from_millis(20);
+
+        // Nothing is handled until the executor runs
+        let result = addr.ask_timeout(Deposit(1), timeout).await;
+        assert_eq!(result, Err(AskError::Timeout));
+        let result = addr.ask_timeout(Deposit(1), timeout).await;
+        assert_eq!(result, Err(AskError::MailboxFull));
+
+        let task = tokio::spawn(async move { executor.run().await });
+        let timeout = Duration::from_secs(5);
+        assert_eq!(addr.ask_timeout(Deposit(1), timeout).await, Ok(2));
+        let result = addr.ask_timeout(Audit, timeout).await;
+        assert_eq!(result, Err(AskError::ActorPanicked));
+
+        assert!(task.await.unwrap_err().is_panic());
+        let result = addr.ask_timeout(Deposit(1), timeout).await;
+        assert_eq!(result, Err(AskError::MailboxClosed));
+    }
+
+    #[tokio::test]
+    async fn abort_set_after_drop_reaches_the_asker() {
+        let (responder, answer) = Responder::<u64>::pair();
+        let abort = responder.abort();
+        drop(responder);
+        abort.set(AskError::ActorPanicked);
+        assert_eq!(answer.recv().await, Err(AskError::ActorPanicked));
+
+        let (responder, answer) = Responder::<u64>::pair();
+        let abort = responder.abort();
+        drop(responder);
+        drop(abort);
+        assert_eq!(answer.recv().await, Err(AskError::HandlerDropped));
+    }
+}
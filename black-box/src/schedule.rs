@@ -0,0 +1,162 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::message::Envelope;
+
+/// The delivery instructions attached to a scheduled [`Envelope`]
+#[derive(Debug)]
+pub(crate) struct Delivery {
+    deadline: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Delivery {
+    pub(crate) fn new(deadline: Instant) -> (Self, ScheduleHandle) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handle = ScheduleHandle {
+            cancelled: cancelled.clone(),
+        };
+
+        (
+            Self {
+                deadline,
+                cancelled,
+            },
+            handle,
+        )
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// A handle to a message scheduled with [`Address::send_after`](crate::Address::send_after) or
+/// [`Address::send_at`](crate::Address::send_at)
+///
+/// Dropping the handle does *not* cancel the delivery.
+#[derive(Debug, Clone)]
+pub struct ScheduleHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduleHandle {
+    /// Cancel the delivery, has no effect if the message was already delivered
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+struct Entry<A> {
+    deadline: Instant,
+    seq: u64,
+    delivery: Delivery,
+    envelope: Envelope<A>,
+}
+
+impl<A> PartialEq for Entry<A> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl<A> Eq for Entry<A> {}
+
+impl<A> PartialOrd for Entry<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A> Ord for Entry<A> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deadline, self.seq).cmp(&(other.deadline, other.seq))
+    }
+}
+
+/// Messages held by the executor until their deadline
+///
+/// Entries with equal deadlines are delivered in the order they were scheduled.
+pub(crate) struct Schedule<A> {
+    seq: u64,
+    entries: BinaryHeap<Reverse<Entry<A>>>,
+}
+
+impl<A> Default for Schedule<A> {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            entries: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<A> std::fmt::Debug for Schedule<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Schedule")
+            .field("len", &self.entries.len())
+            .finish()
+    }
+}
+
+impl<A> Schedule<A> {
+    /// Hold the envelope until its deadline
+    pub(crate) fn insert(&mut self, envelope: Envelope<A>, delivery: Delivery) {
+        if delivery.is_cancelled() {
+            return;
+        }
+
+        self.seq += 1;
+        self.entries.push(Reverse(Entry {
+            deadline: delivery.deadline,
+            seq: self.seq,
+            delivery,
+            envelope,
+        }));
+    }
+
+    /// The deadline of the next message due, ignoring any which have been cancelled
+    pub(crate) fn next_deadline(&mut self) -> Option<Instant> {
+        while let Some(Reverse(entry)) = self.entries.peek() {
+            if !entry.delivery.is_cancelled() {
+                return Some(entry.deadline);
+            }
+            self.entries.pop();
+        }
+
+        None
+    }
+
+    /// Removes every envelope which has not been cancelled, in the order they are due
+    ///
+    /// Each envelope is scheduled again for its original deadline, so it can be handed to another
+    /// executor.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = Envelope<A>> {
+        let entries = std::mem::take(&mut self.entries).into_sorted_vec();
+        entries
+            .into_iter()
+            .rev()
+            .filter(|Reverse(entry)| !entry.delivery.is_cancelled())
+            .map(|Reverse(entry)| entry.envelope.schedule(entry.delivery))
+    }
+
+    /// Removes the next envelope whose deadline has passed
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<Envelope<A>> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+
+        self.entries.pop().map(|Reverse(entry)| entry.envelope)
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+use crate::reply::Origin;
+
+thread_local! {
+    /// The message whose handler is currently being polled on this thread
+    static CURRENT: RefCell<Current> = const { RefCell::new(Current::none()) };
+}
+
+/// What a message sent from within a handler takes from the message being handled
+#[derive(Clone)]
+pub(crate) struct Current {
+    /// The actor handling the message, which is the sender of anything it sends
+    origin: Option<Origin>,
+    correlation_id: Option<Arc<str>>,
+}
+
+impl Current {
+    pub(crate) fn new(origin: Origin, correlation_id: Option<Arc<str>>) -> Self {
+        Self {
+            origin: Some(origin),
+            correlation_id,
+        }
+    }
+
+    const fn none() -> Self {
+        Self {
+            origin: None,
+            correlation_id: None,
+        }
+    }
+
+    /// The actor whose handler is sending, if any
+    pub(crate) fn origin() -> Option<Origin> {
+        CURRENT.with(|current| current.borrow().origin.clone())
+    }
+
+    /// The correlation id of the message being handled, if any
+    pub(crate) fn correlation_id() -> Option<Arc<str>> {
+        CURRENT.with(|current| current.borrow().correlation_id.clone())
+    }
+}
+
+pin_project! {
+    /// Marks messages sent while polling the future as sent in the course of handling `current`
+    pub(crate) struct Scoped<F> {
+        current: Current,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F> Scoped<F> {
+    pub(crate) fn new(current: Current, future: F) -> Self {
+        Self { current, future }
+    }
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let previous = CURRENT.with(|current| current.replace(this.current.clone()));
+
+        // Restored even if the future panics
+        struct Restore(Current);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = std::mem::replace(&mut self.0, Current::none());
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+        let _restore = Restore(previous);
+
+        this.future.poll(cx)
+    }
+}
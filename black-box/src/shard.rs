@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    dead_letter::DeadLetterReason,
+    message::{Envelope, Message},
+    Actor, Address, Handler,
+};
+
+type Factory<K, A> = dyn Fn(&K) -> Address<A> + Send + Sync;
+
+struct Shards<K, A> {
+    factory: Box<Factory<K, A>>,
+    instances: Mutex<HashMap<K, Address<A>>>,
+}
+
+/// A cloneable address which routes each message by key to a per-key actor instance
+///
+/// Instances are created on first use by calling the factory with the key. The factory is
+/// expected to construct an [`Executor`](crate::Executor), spawn it onto your runtime and return
+/// its address. Setting an [`Executor::set_idle_timeout`](crate::Executor::set_idle_timeout) lets
+/// instances stop once they are no longer in use, a message sent to a key whose instance has
+/// stopped creates a fresh one.
+///
+/// The factory is called without holding any lock, so it may itself use the sharded address.
+/// Should two sends race to create the instance for a key, only the first address is kept and the
+/// other is dropped, which stops its executor.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use black_box::*;
+/// struct Request;
+///
+/// struct Session {
+///     user: u64,
+/// }
+///
+/// impl Actor for Session {}
+/// impl Handler<Request> for Session {
+///     async fn handle(&mut self, _msg: Request, _ctx: &Context<Self>) {}
+/// }
+///
+/// # async fn run() {
+/// let sessions = ShardedAddress::new(|user: &u64| {
+///     let (mut executor, addr) = Executor::new(Session { user: *user });
+///     executor.set_idle_timeout(Some(Duration::from_secs(300)));
+///     tokio::spawn(async move { executor.run().await });
+///     addr
+/// });
+///
+/// sessions.send(42, Request).await;
+/// # }
+/// ```
+pub struct ShardedAddress<K, A> {
+    shards: Arc<Shards<K, A>>,
+}
+
+impl<K, A> Clone for ShardedAddress<K, A> {
+    fn clone(&self) -> Self {
+        Self {
+            shards: self.shards.clone(),
+        }
+    }
+}
+
+impl<K, A> std::fmt::Debug for ShardedAddress<K, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardedAddress").finish_non_exhaustive()
+    }
+}
+
+impl<K, A> ShardedAddress<K, A>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn(&K) -> Address<A> + Send + Sync + 'static,
+    {
+        let shards = Shards {
+            factory: Box::new(factory),
+            instances: Mutex::new(HashMap::new()),
+        };
+
+        Self {
+            shards: Arc::new(shards),
+        }
+    }
+
+    /// The address of the instance for `key`, creating it if there is no running instance
+    pub fn address(&self, key: &K) -> Address<A> {
+        if let Some(address) = self.running(key) {
+            return address;
+        }
+
+        let address = (self.shards.factory)(key);
+        let mut instances = self.shards.instances.lock().unwrap();
+        if let Some(existing) = instances.get(key).filter(|addr| !addr.is_closed()) {
+            return existing.clone();
+        }
+
+        // Creating an instance is comparatively rare, so it is a good time to forget any others
+        // which have stopped
+        instances.retain(|_, addr| !addr.is_closed());
+        instances.insert(key.clone(), address.clone());
+        address
+    }
+
+    fn running(&self, key: &K) -> Option<Address<A>> {
+        let instances = self.shards.instances.lock().unwrap();
+        instances.get(key).filter(|addr| !addr.is_closed()).cloned()
+    }
+
+    /// The number of running instances
+    pub fn len(&self) -> usize {
+        let mut instances = self.shards.instances.lock().unwrap();
+        instances.retain(|_, addr| !addr.is_closed());
+        instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget `address`, if it is still the instance for `key`
+    fn evict(&self, key: &K, address: &Address<A>) {
+        let mut instances = self.shards.instances.lock().unwrap();
+        if instances.get(key) == Some(address) {
+            instances.remove(key);
+        }
+    }
+}
+
+impl<K, A> ShardedAddress<K, A>
+where
+    K: Hash + Eq + Clone,
+    A: 'static + Actor + Send,
+{
+    /// Send the message to the instance for `key`
+    ///
+    /// If the instance stops before the message is enqueued, the message is sent to a fresh
+    /// instance instead.
+    pub async fn send<M>(&self, key: K, message: M)
+    where
+        A: Handler<M>,
+        M: Message,
+    {
+        let mut env = Envelope::pack(message);
+
+        // A second attempt only fails if the factory hands back an address which is already
+        // closed, in which case the message is dead lettered rather than retried indefinitely
+        for attempt in 0..2 {
+            let address = self.address(&key);
+            match address.post(env).await {
+                Ok(()) => return,
+                Err(returned) => {
+                    self.evict(&key, &address);
+                    if attempt == 1 {
+                        let reason = DeadLetterReason::MailboxClosed;
+                        return address.mailbox().dead_letter(returned, reason);
+                    }
+                    env = returned;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::{timer::ManualTimer, Context, Executor};
+
+    use super::*;
+
+    struct Visit;
+
+    struct Session {
+        user: u64,
+        visits: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Actor for Session {}
+
+    impl Handler<Visit> for Session {
+        async fn handle(&mut self, _msg: Visit, _ctx: &Context<Self>) {
+            self.visits.lock().unwrap().push(self.user);
+        }
+    }
+
+    fn sessions(
+        timer: ManualTimer,
+        created: Arc<AtomicUsize>,
+        visits: Arc<Mutex<Vec<u64>>>,
+    ) -> ShardedAddress<u64, Session> {
+        ShardedAddress::new(move |user: &u64| {
+            created.fetch_add(1, Ordering::Relaxed);
+            let session = Session {
+                user: *user,
+                visits: visits.clone(),
+            };
+            let (mut executor, addr) = Executor::new(session);
+            executor.set_timer(timer.clone());
+            executor.set_idle_timeout(Some(Duration::from_secs(30)));
+            tokio::spawn(async move { executor.run().await });
+            addr
+        })
+    }
+
+    /// Lets the spawned executors run until they are waiting again
+    async fn settle() {
+        for _ in 0..5 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn instances_are_created_per_key() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let visits = Arc::new(Mutex::new(Vec::new()));
+        let addr = sessions(ManualTimer::new(), created.clone(), visits.clone());
+        addr.send(1, Visit).await;
+        addr.send(2, Visit).await;
+        addr.send(1, Visit).await;
+        settle().await;
+
+        assert_eq!(created.load(Ordering::Relaxed), 2);
+        assert_eq!(addr.len(), 2);
+        let mut visits = visits.lock().unwrap().clone();
+        visits.sort();
+        assert_eq!(visits, vec![1, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn idle_instances_are_replaced() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let visits = Arc::new(Mutex::new(Vec::new()));
+        let timer = ManualTimer::new();
+        let addr = sessions(timer.clone(), created.clone(), visits.clone());
+        addr.send(1, Visit).await;
+        settle().await;
+        timer.advance(Duration::from_secs(60));
+        settle().await;
+        assert!(addr.is_empty());
+
+        addr.send(1, Visit).await;
+        settle().await;
+        assert_eq!(created.load(Ordering::Relaxed), 2);
+        assert_eq!(*visits.lock().unwrap(), vec![1, 1]);
+    }
+
+    #[tokio::test]
+    async fn factory_can_use_the_sharded_address() {
+        let visits = Arc::new(Mutex::new(Vec::new()));
+        let shards: Arc<Mutex<Option<ShardedAddress<u64, Session>>>> = Default::default();
+        let inner = sessions(ManualTimer::new(), Default::default(), visits.clone());
+        let lookup = shards.clone();
+        let addr = ShardedAddress::new(move |user: &u64| {
+            // Would deadlock if the factory ran while holding the instances lock
+            assert!(lookup.lock().unwrap().as_ref().unwrap().is_empty());
+            inner.address(user)
+        });
+        *shards.lock().unwrap() = Some(addr.clone());
+
+        addr.send(7, Visit).await;
+        settle().await;
+        assert_eq!(*visits.lock().unwrap(), vec![7]);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// An actor whose state can be captured and later restored
+///
+/// Snapshots are opaque bytes, so any encoding can be used. Register a [`SnapshotStore`] with
+/// [`Executor::set_snapshot_store`](crate::Executor::set_snapshot_store) to have the executor take
+/// snapshots periodically, or on demand with [`Context::snapshot`](crate::Context::snapshot).
+/// Whatever rebuilds the actor can then start from [`Snapshot::load`] rather than from scratch.
+///
+/// # Example
+///
+/// ```
+/// # use std::io;
+/// # use black_box::*;
+/// struct Cache {
+///     entries: Vec<u64>,
+/// }
+///
+/// impl Snapshot for Cache {
+///     fn snapshot(&self) -> io::Result<Vec<u8>> {
+///         Ok(self.entries.iter().flat_map(|e| e.to_le_bytes()).collect())
+///     }
+///
+///     fn restore(bytes: &[u8]) -> io::Result<Self> {
+///         let entries = bytes
+///             .chunks_exact(8)
+///             .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+///             .collect();
+///         Ok(Self { entries })
+///     }
+/// }
+///
+/// # fn rebuild(store: &InMemorySnapshotStore) -> io::Result<Cache> {
+/// let cache = Cache::load(store)?.unwrap_or(Cache { entries: vec![] });
+/// # Ok(cache)
+/// # }
+/// ```
+pub trait Snapshot: Sized {
+    /// Capture the current state
+    fn snapshot(&self) -> io::Result<Vec<u8>>;
+
+    /// Rebuild the state from bytes produced by [`Snapshot::snapshot`]
+    fn restore(bytes: &[u8]) -> io::Result<Self>;
+
+    /// Invoked when the executor fails to take or store a snapshot
+    fn snapshot_failed(&mut self, _err: io::Error) {}
+
+    /// Restore from the latest snapshot in the store, if there is one
+    fn load(store: &dyn SnapshotStore) -> io::Result<Option<Self>> {
+        match store.latest()? {
+            Some(bytes) => Self::restore(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Somewhere to keep [`Snapshot`]s
+pub trait SnapshotStore: Send + Sync + 'static {
+    fn save(&self, snapshot: &[u8]) -> io::Result<()>;
+
+    /// The most recently saved snapshot
+    fn latest(&self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A [`SnapshotStore`] held in memory, which only keeps the latest snapshot
+///
+/// Clones share the same snapshot.
+#[derive(Debug, Default, Clone)]
+pub struct InMemorySnapshotStore {
+    latest: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl InMemorySnapshotStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SnapshotStore for InMemorySnapshotStore {
+    fn save(&self, snapshot: &[u8]) -> io::Result<()> {
+        *self.latest.lock().unwrap() = Some(snapshot.to_vec());
+        Ok(())
+    }
+
+    fn latest(&self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.latest.lock().unwrap().clone())
+    }
+}
+
+/// A [`SnapshotStore`] which writes each snapshot to its own file in a directory
+///
+/// Snapshots are written to a temporary file and renamed into place, so a crash while saving
+/// never leaves a partial snapshot behind. Only the most recent snapshots are kept, see
+/// [`Self::set_retain`].
+///
+/// Every save is synced to disk before returning, which blocks the thread running the actor's
+/// executor until the disk has caught up. Actors with large or frequent snapshots are best run on
+/// their own thread, or given a [`SnapshotStore`] which hands the writes to a background thread.
+#[derive(Debug)]
+pub struct DirectorySnapshotStore {
+    dir: PathBuf,
+    retain: usize,
+    /// Serializes saves, so concurrent saves cannot pick the same sequence number
+    lock: Mutex<()>,
+}
+
+impl DirectorySnapshotStore {
+    const PREFIX: &'static str = "snapshot-";
+
+    /// Open the store in `dir`, creating the directory if it does not exist
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            retain: 2,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// The number of snapshots to keep, older snapshots are deleted after each save
+    ///
+    /// Defaults to 2, and is never less than 1.
+    pub fn set_retain(&mut self, retain: usize) {
+        self.retain = retain.max(1);
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The sequence numbers of the snapshots in the directory, oldest first
+    fn sequences(&self) -> io::Result<Vec<u64>> {
+        let mut sequences = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let seq = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(Self::PREFIX))
+                .and_then(|seq| seq.parse::<u64>().ok());
+            sequences.extend(seq);
+        }
+
+        sequences.sort_unstable();
+        Ok(sequences)
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{}{seq:020}", Self::PREFIX))
+    }
+}
+
+impl SnapshotStore for DirectorySnapshotStore {
+    fn save(&self, snapshot: &[u8]) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let mut sequences = self.sequences()?;
+        let seq = sequences.last().map_or(0, |seq| seq + 1);
+
+        let tmp = self.dir.join(format!(".{}{seq:020}.tmp", Self::PREFIX));
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(snapshot)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.path(seq))?;
+
+        sequences.push(seq);
+        let stale = sequences.len().saturating_sub(self.retain);
+        for seq in &sequences[..stale] {
+            fs::remove_file(self.path(*seq))?;
+        }
+
+        Ok(())
+    }
+
+    fn latest(&self) -> io::Result<Option<Vec<u8>>> {
+        match self.sequences()?.last() {
+            Some(seq) => fs::read(self.path(*seq)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn take<A: Snapshot>(actor: &mut A, store: &dyn SnapshotStore) {
+    if let Err(err) = actor.snapshot().and_then(|bytes| store.save(&bytes)) {
+        actor.snapshot_failed(err);
+    }
+}
+
+/// The snapshot configuration of an executor
+pub(crate) struct Snapshots<A> {
+    store: Arc<dyn SnapshotStore>,
+    take: fn(&mut A, &dyn SnapshotStore),
+    interval: Option<Duration>,
+    next: Option<Instant>,
+}
+
+impl<A> std::fmt::Debug for Snapshots<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Snapshots")
+            .field("interval", &self.interval)
+            .field("next", &self.next)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: Snapshot> Snapshots<A> {
+    pub(crate) fn new<S: SnapshotStore>(store: S, interval: Option<Duration>) -> Self {
+        Self {
+            store: Arc::new(store),
+            take: take::<A>,
+            interval,
+            next: None,
+        }
+    }
+}
+
+impl<A> Snapshots<A> {
+    /// When the next periodic snapshot is due, starting the interval if it has not been started
+    pub(crate) fn next(&mut self, now: Instant) -> Option<Instant> {
+        let interval = self.interval?;
+        Some(*self.next.get_or_insert(now + interval))
+    }
+
+    /// Take a snapshot, and restart the interval
+    pub(crate) fn take(&mut self, actor: &mut A, now: Instant) {
+        (self.take)(actor, &*self.store);
+        self.next = self.interval.map(|interval| now + interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{timer::ManualTimer, Actor, Context, Executor, Handler};
+
+    use super::*;
+
+    struct Insert(u8);
+    struct Save;
+
+    #[derive(Default)]
+    struct Cache(Vec<u8>);
+
+    impl Actor for Cache {}
+
+    impl Snapshot for Cache {
+        fn snapshot(&self) -> io::Result<Vec<u8>> {
+            Ok(self.0.clone())
+        }
+
+        fn restore(bytes: &[u8]) -> io::Result<Self> {
+            Ok(Self(bytes.to_vec()))
+        }
+    }
+
+    impl Handler<Insert> for Cache {
+        async fn handle(&mut self, msg: Insert, _ctx: &Context<Self>) {
+            self.0.push(msg.0);
+        }
+    }
+
+    impl Handler<Save> for Cache {
+        async fn handle(&mut self, _msg: Save, ctx: &Context<Self>) {
+            ctx.snapshot();
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshot_on_demand() {
+        let store = InMemorySnapshotStore::new();
+        let (mut executor, addr) = Executor::new(Cache::default());
+        executor.set_snapshot_store(store.clone(), None);
+        addr.send(Insert(1)).await;
+        addr.send(Save).await;
+        addr.send(Insert(2)).await;
+        drop(addr);
+
+        assert!(executor.run().await.is_err());
+        let restored = Cache::load(&store).unwrap().unwrap();
+        assert_eq!(restored.0, vec![1]);
+    }
+
+    /// Lets the executor run until it is waiting again
+    async fn settle() {
+        for _ in 0..5 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshot_periodically() {
+        let timer = ManualTimer::new();
+        let store = InMemorySnapshotStore::new();
+        let (mut executor, addr) = Executor::new(Cache::default());
+        executor.set_timer(timer.clone());
+        executor.set_snapshot_store(store.clone(), Some(Duration::from_millis(10)));
+        addr.send(Insert(7)).await;
+
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(store.latest().unwrap(), None);
+
+        timer.advance(Duration::from_millis(10));
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(store.latest().unwrap(), Some(vec![7]));
+
+        // The interval restarts once a snapshot has been taken
+        addr.send(Insert(8)).await;
+        timer.advance(Duration::from_millis(5));
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(store.latest().unwrap(), Some(vec![7]));
+
+        timer.advance(Duration::from_millis(5));
+        assert_eq!(executor.run_against(settle()).await, Ok(true));
+        assert_eq!(store.latest().unwrap(), Some(vec![7, 8]));
+    }
+
+    #[test]
+    fn directory_store_keeps_latest() {
+        let dir = std::env::temp_dir().join(format!("black-box-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut store = DirectorySnapshotStore::open(&dir).unwrap();
+        store.set_retain(2);
+        assert!(Cache::load(&store).unwrap().is_none());
+
+        for i in 0..5 {
+            store.save(&[i]).unwrap();
+        }
+        assert_eq!(store.latest().unwrap(), Some(vec![4]));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Reopening continues from the existing snapshots
+        let store = DirectorySnapshotStore::open(&dir).unwrap();
+        store.save(&[5]).unwrap();
+        assert_eq!(Cache::load(&store).unwrap().unwrap().0, vec![5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
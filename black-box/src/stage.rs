@@ -0,0 +1,449 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use crate::{Actor, Executor};
+
+const DEFAULT_BUDGET: usize = 32;
+
+pub(crate) type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+thread_local! {
+    /// The number of messages the actor currently being polled by a stage may still handle
+    /// before yielding, `None` outside of a stage
+    static BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Yield back to the [`Stage`] once the current actor has used up its budget
+///
+/// Awaited by the executor before each message, this has no effect outside of a stage.
+pub(crate) async fn cooperate() {
+    let exhausted = BUDGET.with(|budget| match budget.get() {
+        Some(0) => true,
+        Some(n) => {
+            budget.set(Some(n - 1));
+            false
+        }
+        None => false,
+    });
+
+    if exhausted {
+        YieldNow(false).await;
+    }
+}
+
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// State shared by a stage, its handles, and the wakers of its actors
+#[derive(Default)]
+struct Shared {
+    /// Indices of the actors which have been woken, in the order they were woken
+    ready: Mutex<VecDeque<usize>>,
+    incoming: Mutex<Vec<Job>>,
+    handles: AtomicUsize,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Shared {
+    fn wake(&self) {
+        if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Wakes a single actor on a stage
+struct Slot {
+    index: usize,
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Wake for Slot {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.shared.ready.lock().unwrap().push_back(self.index);
+            self.shared.wake();
+        }
+    }
+}
+
+struct Entry {
+    job: Job,
+    slot: Arc<Slot>,
+    waker: Waker,
+}
+
+/// Drives many executors, of any actor type, from a single future
+///
+/// Each executor would otherwise be spawned as its own task. A stage only polls the actors which
+/// have been woken, in the order they were woken, and each may handle a limited number of
+/// messages before the others get a turn, see [`Self::set_budget`]. An actor which panics is
+/// stopped, the others keep running.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Reading(f64);
+///
+/// struct Device;
+/// impl Actor for Device {}
+/// impl Handler<Reading> for Device {
+///     async fn handle(&mut self, _msg: Reading, _ctx: &Context<Self>) {}
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut stage = Stage::new();
+/// let mut devices = Vec::new();
+/// for _ in 0..1_000 {
+///     let (executor, address) = Executor::new(Device);
+///     stage.spawn(executor);
+///     devices.push(address);
+/// }
+///
+/// tokio::spawn(async move { stage.run().await });
+/// devices[42].send(Reading(21.5)).await;
+/// # }
+/// ```
+pub struct Stage {
+    shared: Arc<Shared>,
+    entries: Vec<Option<Entry>>,
+    /// Indices of vacant entries, to be reused
+    vacant: Vec<usize>,
+    len: usize,
+    budget: usize,
+}
+
+impl std::fmt::Debug for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stage")
+            .field("len", &self.len)
+            .field("budget", &self.budget)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Stage {
+    fn default() -> Self {
+        Self {
+            shared: Default::default(),
+            entries: Vec::new(),
+            vacant: Vec::new(),
+            len: 0,
+            budget: DEFAULT_BUDGET,
+        }
+    }
+}
+
+impl Stage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of messages an actor may handle before yielding to the others, by default
+    /// this is 32
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget.max(1);
+    }
+
+    /// Add an executor to the stage, it is run once the stage is
+    pub fn spawn<A>(&mut self, executor: Executor<A>)
+    where
+        A: 'static + Actor + Send,
+    {
+        let job = run(executor);
+        self.insert(job);
+    }
+
+    /// A handle which can add executors to the stage while it is running
+    pub fn handle(&self) -> StageHandle {
+        StageHandle::new(self.shared.clone())
+    }
+
+    /// The number of actors which have not yet stopped
+    pub fn len(&self) -> usize {
+        self.len + self.shared.incoming.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run every actor on the stage
+    ///
+    /// Resolves once every actor has stopped, and every [`StageHandle`] has been dropped.
+    pub async fn run(&mut self) {
+        std::future::poll_fn(|cx| self.poll_actors(cx)).await
+    }
+
+    fn insert(&mut self, job: Job) {
+        let index = self.vacant.pop().unwrap_or(self.entries.len());
+        let slot = Arc::new(Slot {
+            index,
+            queued: AtomicBool::new(false),
+            shared: self.shared.clone(),
+        });
+        let waker = Waker::from(slot.clone());
+        waker.wake_by_ref();
+
+        let entry = Entry { job, slot, waker };
+        match self.entries.get_mut(index) {
+            Some(vacant) => *vacant = Some(entry),
+            None => self.entries.push(Some(entry)),
+        }
+        self.len += 1;
+    }
+
+    fn poll_actors(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        {
+            let mut waker = self.shared.waker.lock().unwrap();
+            if !waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+                *waker = Some(cx.waker().clone());
+            }
+        }
+
+        let incoming = std::mem::take(&mut *self.shared.incoming.lock().unwrap());
+        for job in incoming {
+            self.insert(job);
+        }
+
+        // Actors woken while this round is underway wait for the next, so none can starve another
+        let round = self.shared.ready.lock().unwrap().len();
+        for _ in 0..round {
+            let Some(index) = self.shared.ready.lock().unwrap().pop_front() else {
+                break;
+            };
+            self.poll_entry(index);
+        }
+
+        if !self.shared.ready.lock().unwrap().is_empty() {
+            // Yield to the runtime between rounds
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let idle = self.shared.handles.load(Ordering::Acquire) == 0
+            && self.shared.incoming.lock().unwrap().is_empty();
+        match self.len == 0 && idle {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+
+    fn poll_entry(&mut self, index: usize) {
+        // The index may be stale if the actor it was queued for has since stopped
+        let Some(entry) = self.entries.get_mut(index).and_then(Option::as_mut) else {
+            return;
+        };
+
+        // Cleared first, so the actor can be queued again by a wake while it is polled
+        entry.slot.queued.store(false, Ordering::Release);
+        let mut cx = Context::from_waker(&entry.waker);
+        let previous = BUDGET.with(|budget| budget.replace(Some(self.budget)));
+
+        // Restored even if the job panics
+        struct Restore(Option<usize>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                BUDGET.with(|budget| budget.set(self.0));
+            }
+        }
+        let restore = Restore(previous);
+
+        // A panicking actor is stopped, without taking the rest of the stage down with it
+        let poll = catch_unwind(AssertUnwindSafe(|| entry.job.as_mut().poll(&mut cx)));
+        drop(restore);
+
+        if poll.map_or(true, |poll| poll.is_ready()) {
+            self.entries[index] = None;
+            self.vacant.push(index);
+            self.len -= 1;
+        }
+    }
+}
+
+fn run<A>(mut executor: Executor<A>) -> Job
+where
+    A: 'static + Actor + Send,
+{
+    Box::pin(async move {
+        let _ = executor.run().await;
+    })
+}
+
+/// A cloneable handle for adding executors to a running [`Stage`]
+///
+/// While any handle exists, [`Stage::run`] keeps running even once every actor has stopped.
+pub struct StageHandle {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for StageHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StageHandle").finish_non_exhaustive()
+    }
+}
+
+impl StageHandle {
+    fn new(shared: Arc<Shared>) -> Self {
+        shared.handles.fetch_add(1, Ordering::AcqRel);
+        Self { shared }
+    }
+
+    /// Add an executor to the stage
+    pub fn spawn<A>(&self, executor: Executor<A>)
+    where
+        A: 'static + Actor + Send,
+    {
+        self.spawn_job(run(executor));
+    }
+
+    /// Add a future to the stage, which runs alongside the actors
+    pub(crate) fn spawn_job(&self, job: Job) {
+        self.shared.incoming.lock().unwrap().push(job);
+        self.shared.wake();
+    }
+}
+
+impl Clone for StageHandle {
+    fn clone(&self) -> Self {
+        Self::new(self.shared.clone())
+    }
+}
+
+impl Drop for StageHandle {
+    fn drop(&mut self) {
+        self.shared.handles.fetch_sub(1, Ordering::AcqRel);
+        self.shared.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::Handler;
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    struct Tick;
+
+    struct Busy(Log);
+    impl Actor for Busy {}
+    impl Handler<Tick> for Busy {
+        async fn handle(&mut self, _msg: Tick, _ctx: &crate::Context<Self>) {
+            self.0.lock().unwrap().push("busy");
+        }
+    }
+
+    struct Quiet(Log);
+    impl Actor for Quiet {}
+    impl Handler<Tick> for Quiet {
+        async fn handle(&mut self, _msg: Tick, _ctx: &crate::Context<Self>) {
+            self.0.lock().unwrap().push("quiet");
+        }
+    }
+
+    #[tokio::test]
+    async fn busy_actors_do_not_starve_others() {
+        let log = Log::default();
+        let mut stage = Stage::new();
+        stage.set_budget(4);
+
+        let (busy, busy_addr) = Executor::new(Busy(log.clone()));
+        let (quiet, quiet_addr) = Executor::new(Quiet(log.clone()));
+        for _ in 0..50 {
+            busy_addr.send(Tick).await;
+        }
+        quiet_addr.send(Tick).await;
+        stage.spawn(busy);
+        stage.spawn(quiet);
+        assert_eq!(stage.len(), 2);
+
+        drop((busy_addr, quiet_addr));
+        stage.run().await;
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 51);
+        assert_eq!(log.iter().position(|&name| name == "quiet"), Some(4));
+        assert!(stage.is_empty());
+    }
+
+    struct Faulty;
+    impl Actor for Faulty {}
+    impl Handler<Tick> for Faulty {
+        async fn handle(&mut self, _msg: Tick, _ctx: &crate::Context<Self>) {
+            panic!("faulty");
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_actors_only_stop_themselves() {
+        let log = Log::default();
+        let mut stage = Stage::new();
+
+        let (faulty, faulty_addr) = Executor::new(Faulty);
+        let (quiet, quiet_addr) = Executor::new(Quiet(log.clone()));
+        faulty_addr.send(Tick).await;
+        quiet_addr.send(Tick).await;
+        stage.spawn(faulty);
+        stage.spawn(quiet);
+
+        drop(quiet_addr);
+        stage.run().await;
+
+        assert_eq!(*log.lock().unwrap(), ["quiet"]);
+        assert!(faulty_addr.is_closed());
+        assert!(stage.is_empty());
+        assert_eq!(BUDGET.with(Cell::get), None);
+    }
+
+    #[tokio::test]
+    async fn handles_add_actors_while_running() {
+        let log = Log::default();
+        let mut stage = Stage::new();
+        let handle = stage.handle();
+        let run = tokio::spawn(async move { stage.run().await });
+
+        let (quiet, addr) = Executor::new(Quiet(log.clone()));
+        handle.spawn(quiet);
+        addr.send(Tick).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*log.lock().unwrap(), ["quiet"]);
+        assert!(!run.is_finished());
+
+        drop((addr, handle));
+        run.await.unwrap();
+    }
+}
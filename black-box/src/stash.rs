@@ -0,0 +1,124 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::message::Envelope;
+
+struct Inner<A> {
+    stashed: VecDeque<Envelope<A>>,
+    /// Unstashed envelopes, waiting to be moved to the front of the executor's backlog
+    replay: VecDeque<Envelope<A>>,
+}
+
+/// Messages deferred by a handler with [`Context::stash`](crate::Context::stash), shared by every
+/// clone of the actor's context
+pub(crate) struct Stash<A>(Arc<Mutex<Inner<A>>>);
+
+impl<A> Clone for Stash<A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<A> Default for Stash<A> {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            stashed: VecDeque::new(),
+            replay: VecDeque::new(),
+        })))
+    }
+}
+
+impl<A> std::fmt::Debug for Stash<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stash")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> Stash<A> {
+    pub(crate) fn push(&self, env: Envelope<A>) {
+        self.0.lock().unwrap().stashed.push_back(env);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.lock().unwrap().stashed.len()
+    }
+
+    /// Remove the envelopes which are still stashed, leaving any waiting to be replayed
+    pub(crate) fn take(&self) -> VecDeque<Envelope<A>> {
+        std::mem::take(&mut self.0.lock().unwrap().stashed)
+    }
+
+    /// Stash the envelopes again, after any which are already stashed
+    pub(crate) fn extend(&self, envelopes: VecDeque<Envelope<A>>) {
+        self.0.lock().unwrap().stashed.extend(envelopes);
+    }
+
+    /// Queue every stashed envelope for replay, after any which are already waiting to be replayed
+    pub(crate) fn unstash_all(&self) {
+        let mut inner = self.0.lock().unwrap();
+        let Inner { stashed, replay } = &mut *inner;
+        replay.append(stashed);
+    }
+
+    /// Move the envelopes waiting to be replayed to the front of `backlog`, preserving their order
+    pub(crate) fn replay_into(&self, backlog: &mut VecDeque<Envelope<A>>) {
+        let mut inner = self.0.lock().unwrap();
+        while let Some(env) = inner.replay.pop_back() {
+            backlog.push_front(env);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Actor, Context, Executor, Handler};
+
+    struct Ready;
+    struct Query(usize);
+
+    #[derive(Default)]
+    struct Service {
+        ready: bool,
+        answered: Vec<usize>,
+    }
+
+    impl Actor for Service {}
+
+    impl Handler<Ready> for Service {
+        async fn handle(&mut self, _msg: Ready, ctx: &Context<Self>) {
+            self.ready = true;
+            ctx.unstash_all();
+        }
+    }
+
+    impl Handler<Query> for Service {
+        async fn handle(&mut self, msg: Query, ctx: &Context<Self>) {
+            if !self.ready {
+                return ctx.stash(msg);
+            }
+
+            self.answered.push(msg.0);
+            if self.answered.len() == 4 {
+                assert_eq!(ctx.stashed(), 0);
+                ctx.shutdown();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stashed_messages_replay_in_order() {
+        let (mut executor, addr) = Executor::new(Service::default());
+        addr.send(Query(1)).await;
+        addr.send(Query(2)).await;
+        addr.send(Ready).await;
+        addr.send(Query(4)).await;
+        addr.send(Query(3)).await;
+
+        assert!(executor.run().await.is_ok());
+        assert_eq!(executor.actor_ref().answered, vec![1, 2, 4, 3]);
+    }
+}
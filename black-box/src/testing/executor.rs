@@ -0,0 +1,207 @@
+use std::any::TypeId;
+
+use crate::{executor::State, Actor, Address, Executor};
+
+/// An [`Executor`] which is driven manually, one message at a time
+///
+/// Rather than spawning the actor and waiting for messages to settle, a test can call
+/// [`TestExecutor::step`] to process exactly one message, then inspect the actor with
+/// [`TestExecutor::actor_ref`] and the remaining mailbox contents before the next step.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::{*, testing::*};
+/// struct Increment;
+///
+/// #[derive(Default)]
+/// struct Counter(usize);
+/// impl Actor for Counter {}
+/// impl Handler<Increment> for Counter {
+///     async fn handle(&mut self, _msg: Increment, _ctx: &Context<Self>) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (mut executor, addr) = TestExecutor::new(Counter::default());
+/// addr.send(Increment).await;
+/// addr.send(Increment).await;
+///
+/// assert_eq!(executor.queued::<Increment>(), 2);
+/// assert!(executor.step().await);
+/// assert_eq!(executor.actor_ref().0, 1);
+/// assert_eq!(executor.queued::<Increment>(), 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TestExecutor<A> {
+    executor: Executor<A>,
+    started: bool,
+}
+
+impl<A> TestExecutor<A> {
+    pub fn new(actor: A) -> (Self, Address<A>) {
+        let (executor, address) = Executor::new(actor);
+        (Self::from_executor(executor), address)
+    }
+
+    /// Drive an already configured executor step by step
+    pub fn from_executor(executor: Executor<A>) -> Self {
+        Self {
+            executor,
+            started: false,
+        }
+    }
+}
+
+impl<A> TestExecutor<A>
+where
+    A: Actor,
+{
+    pub fn actor_ref(&self) -> &A {
+        self.executor.actor_ref()
+    }
+
+    pub fn actor_mut(&mut self) -> &mut A {
+        self.executor.actor_mut()
+    }
+
+    /// The number of messages waiting in the mailbox
+    pub fn mailbox_len(&mut self) -> usize {
+        self.executor.queued().count()
+    }
+
+    /// The number of messages of type `M` waiting in the mailbox
+    pub fn queued<M: 'static>(&mut self) -> usize {
+        let id = TypeId::of::<M>();
+        self.executor
+            .queued()
+            .filter(|env| env.type_id() == id)
+            .count()
+    }
+
+    /// The type names of the messages waiting in the mailbox, in the order they will be handled
+    pub fn queued_types(&mut self) -> Vec<&'static str> {
+        self.executor.queued().map(|env| env.type_name()).collect()
+    }
+
+    /// Whether the actor has requested shutdown, either from its context or a
+    /// [`ShutdownHandle`](crate::ShutdownHandle)
+    pub fn is_shutdown(&mut self) -> bool {
+        self.executor.receive_state() == State::Shutdown
+    }
+
+    /// Process exactly one message, returning `false` if there was nothing to process
+    ///
+    /// Scheduled messages which are due take precedence over the mailbox, those which are not
+    /// yet due are held back and do not count as a step. [`Actor::starting`] is invoked before
+    /// the first step. As with the real executor, nothing more is processed once the actor has
+    /// requested shutdown.
+    pub async fn step(&mut self) -> bool {
+        if !self.started {
+            self.started = true;
+            self.executor.start().await;
+        }
+
+        self.executor.step().await
+    }
+
+    /// Step until there are no messages left to process, returning the number of steps taken
+    pub async fn run_until_idle(&mut self) -> usize {
+        let mut steps = 0;
+        while self.step().await {
+            steps += 1;
+        }
+
+        steps
+    }
+
+    /// Invoke [`Actor::stopping`], as the executor would when exiting
+    pub async fn stop(&mut self) {
+        self.executor.stop().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{Context, Handler};
+
+    use super::*;
+
+    struct Add(usize);
+    struct Stop;
+
+    #[derive(Default)]
+    struct Sum {
+        total: usize,
+        started: bool,
+    }
+
+    impl Actor for Sum {
+        async fn starting(&mut self, _ctx: &Context<Self>) {
+            self.started = true;
+        }
+    }
+
+    impl Handler<Add> for Sum {
+        async fn handle(&mut self, msg: Add, _ctx: &Context<Self>) {
+            self.total += msg.0;
+        }
+    }
+
+    impl Handler<Stop> for Sum {
+        async fn handle(&mut self, _msg: Stop, ctx: &Context<Self>) {
+            ctx.shutdown();
+        }
+    }
+
+    #[tokio::test]
+    async fn steps_one_message_at_a_time() {
+        let (mut executor, addr) = TestExecutor::new(Sum::default());
+        addr.send(Add(1)).await;
+        addr.send(Stop).await;
+        addr.send(Add(2)).await;
+
+        assert_eq!(
+            executor.queued_types(),
+            vec![
+                std::any::type_name::<Add>(),
+                std::any::type_name::<Stop>(),
+                std::any::type_name::<Add>()
+            ]
+        );
+        assert!(!executor.actor_ref().started);
+
+        assert!(executor.step().await);
+        assert!(executor.actor_ref().started);
+        assert_eq!(executor.actor_ref().total, 1);
+        assert_eq!(executor.queued::<Add>(), 1);
+        assert!(!executor.is_shutdown());
+
+        assert!(executor.step().await);
+        assert!(executor.is_shutdown());
+
+        assert!(!executor.step().await);
+        assert_eq!(executor.run_until_idle().await, 0);
+        assert_eq!(executor.actor_ref().total, 1);
+        assert_eq!(executor.queued::<Add>(), 1);
+    }
+
+    #[tokio::test]
+    async fn scheduled_messages_wait_until_due() {
+        let (mut executor, addr) = TestExecutor::new(Sum::default());
+        addr.send_after(Add(5), Duration::from_millis(20)).await;
+
+        assert_eq!(executor.mailbox_len(), 1);
+        assert!(!executor.step().await);
+        assert_eq!(executor.mailbox_len(), 0);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(executor.step().await);
+        assert_eq!(executor.actor_ref().total, 5);
+    }
+}
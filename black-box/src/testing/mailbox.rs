@@ -0,0 +1,145 @@
+use std::{any::TypeId, collections::VecDeque, sync::Arc};
+
+use async_channel::Receiver;
+
+use crate::{mailbox::Mailbox, message::Envelope, Address};
+
+/// A mailbox with no actor behind it, which records everything sent to its [`Address`]
+///
+/// Useful for asserting on the messages an actor under test sends to its collaborators.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::{*, testing::*};
+/// struct Notify(&'static str);
+///
+/// struct Listener;
+/// impl Actor for Listener {}
+/// impl Handler<Notify> for Listener {
+///     async fn handle(&mut self, _msg: Notify, _ctx: &Context<Self>) {}
+/// }
+///
+/// let (mut mailbox, addr) = MockMailbox::<Listener>::new();
+/// addr.try_send(Notify("hello"));
+///
+/// let sent: Vec<Notify> = mailbox.take();
+/// assert_eq!(sent[0].0, "hello");
+/// ```
+#[derive(Debug)]
+pub struct MockMailbox<A> {
+    receiver: Receiver<Envelope<A>>,
+    mailbox: Arc<Mailbox>,
+    recorded: VecDeque<Envelope<A>>,
+}
+
+impl<A> MockMailbox<A> {
+    /// Construct a new mailbox, along with an address which records into it
+    ///
+    /// The mailbox is unbounded, so sends to the address never wait for capacity.
+    pub fn new() -> (Self, Address<A>) {
+        let (sender, receiver) = async_channel::unbounded();
+        let mailbox = Arc::new(Mailbox::new(None));
+        let me = Self {
+            receiver,
+            mailbox: mailbox.clone(),
+            recorded: VecDeque::new(),
+        };
+
+        (me, Address::new(sender, mailbox))
+    }
+
+    /// The number of messages recorded
+    pub fn len(&mut self) -> usize {
+        self.fill();
+        self.recorded.len()
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of recorded messages of type `M`
+    pub fn count<M: 'static>(&mut self) -> usize {
+        self.fill();
+        let id = TypeId::of::<M>();
+        self.recorded
+            .iter()
+            .filter(|env| env.type_id() == id)
+            .count()
+    }
+
+    /// The type names of the recorded messages, in the order they were sent
+    pub fn type_names(&mut self) -> Vec<&'static str> {
+        self.fill();
+        self.recorded.iter().map(|env| env.type_name()).collect()
+    }
+
+    /// Removes and returns every recorded message of type `M`, in the order they were sent
+    pub fn take<M: 'static>(&mut self) -> Vec<M> {
+        self.fill();
+        let mut taken = Vec::new();
+        let mut rest = VecDeque::with_capacity(self.recorded.len());
+        for env in self.recorded.drain(..) {
+            match env.downcast() {
+                Ok(message) => taken.push(message),
+                Err(env) => rest.push_back(env),
+            }
+        }
+        self.recorded = rest;
+
+        taken
+    }
+
+    /// Discards everything recorded so far
+    pub fn clear(&mut self) {
+        self.fill();
+        self.recorded.clear();
+    }
+
+    fn fill(&mut self) {
+        while let Ok(mut env) = self.receiver.try_recv() {
+            self.mailbox.release(&mut env);
+            self.recorded.push_back(env);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Actor, Context, Handler};
+
+    use super::*;
+
+    struct Ping(u8);
+    struct Pong;
+    struct Peer;
+
+    impl Actor for Peer {}
+
+    impl Handler<Ping> for Peer {
+        async fn handle(&mut self, _msg: Ping, _ctx: &Context<Self>) {}
+    }
+
+    impl Handler<Pong> for Peer {
+        async fn handle(&mut self, _msg: Pong, _ctx: &Context<Self>) {}
+    }
+
+    #[tokio::test]
+    async fn records_by_type() {
+        let (mut mailbox, addr) = MockMailbox::<Peer>::new();
+        addr.send(Ping(1)).await;
+        addr.send(Pong).await;
+        addr.send(Ping(2)).await;
+
+        assert_eq!(mailbox.len(), 3);
+        assert_eq!(mailbox.count::<Pong>(), 1);
+
+        let pings: Vec<u8> = mailbox.take::<Ping>().into_iter().map(|p| p.0).collect();
+        assert_eq!(pings, vec![1, 2]);
+        assert_eq!(mailbox.type_names(), vec![std::any::type_name::<Pong>()]);
+
+        mailbox.clear();
+        assert!(mailbox.is_empty());
+    }
+}
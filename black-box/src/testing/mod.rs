@@ -0,0 +1,11 @@
+//! Utilities for testing actors without spawning them onto a runtime
+//!
+//! Enabled with the `testing` feature.
+
+mod executor;
+mod mailbox;
+mod simulation;
+
+pub use executor::TestExecutor;
+pub use mailbox::MockMailbox;
+pub use simulation::{SimTimer, Simulation, Spawner};
@@ -0,0 +1,508 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::timer::{Sleep, Timer};
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A small, seedable PRNG (SplitMix64), good enough for picking which task runs next
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[derive(Debug)]
+struct Sleeper {
+    deadline: Instant,
+    seq: u64,
+    sleep_id: u64,
+    waker: Waker,
+}
+
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl Eq for Sleeper {}
+
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sleeper {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deadline, self.seq).cmp(&(other.deadline, other.seq))
+    }
+}
+
+#[derive(Debug)]
+struct Clock {
+    start: Instant,
+    now: Instant,
+    seq: u64,
+    sleepers: BinaryHeap<Reverse<Sleeper>>,
+}
+
+impl Clock {
+    /// Advance to the earliest deadline, waking every sleeper which is then due
+    ///
+    /// Returns `false` if nobody is sleeping.
+    fn advance(&mut self) -> bool {
+        let Some(Reverse(next)) = self.sleepers.peek() else {
+            return false;
+        };
+        self.now = self.now.max(next.deadline);
+
+        while self
+            .sleepers
+            .peek()
+            .is_some_and(|Reverse(sleeper)| sleeper.deadline <= self.now)
+        {
+            let Reverse(sleeper) = self.sleepers.pop().unwrap();
+            sleeper.waker.wake();
+        }
+
+        true
+    }
+}
+
+/// A [`Timer`] driven by the virtual clock of a [`Simulation`]
+///
+/// Time only moves forward when every task in the simulation is idle, at which point the clock
+/// jumps straight to the next deadline. Hand it to each executor with
+/// [`Executor::set_timer`](crate::Executor::set_timer).
+#[derive(Debug, Clone)]
+pub struct SimTimer {
+    clock: Arc<Mutex<Clock>>,
+}
+
+impl Timer for SimTimer {
+    fn now(&self) -> Instant {
+        self.clock.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let id = {
+            let mut clock = self.clock.lock().unwrap();
+            clock.seq += 1;
+            clock.seq
+        };
+
+        Box::pin(SimSleep {
+            clock: self.clock.clone(),
+            id,
+            deadline,
+            registered: None,
+        })
+    }
+}
+
+struct SimSleep {
+    clock: Arc<Mutex<Clock>>,
+    id: u64,
+    deadline: Instant,
+    registered: Option<Waker>,
+}
+
+impl Drop for SimSleep {
+    fn drop(&mut self) {
+        // A sleep which is dropped early, like the deadline of a handler which completed in time,
+        // must not drag the clock forward once everything is idle
+        if self.registered.is_some() {
+            let id = self.id;
+            let mut clock = self.clock.lock().unwrap();
+            clock
+                .sleepers
+                .retain(|Reverse(sleeper)| sleeper.sleep_id != id);
+        }
+    }
+}
+
+impl Future for SimSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut clock = this.clock.lock().unwrap();
+        if clock.now >= this.deadline {
+            return Poll::Ready(());
+        }
+
+        if !this
+            .registered
+            .as_ref()
+            .is_some_and(|waker| waker.will_wake(cx.waker()))
+        {
+            this.registered = Some(cx.waker().clone());
+            clock.seq += 1;
+            let sleeper = Sleeper {
+                deadline: this.deadline,
+                seq: clock.seq,
+                sleep_id: this.id,
+                waker: cx.waker().clone(),
+            };
+            clock.sleepers.push(Reverse(sleeper));
+        }
+
+        Poll::Pending
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.lock().unwrap().insert(self.id);
+    }
+}
+
+/// A cloneable handle for spawning tasks into a running [`Simulation`]
+#[derive(Clone)]
+pub struct Spawner {
+    spawned: Arc<Mutex<Vec<Task>>>,
+}
+
+impl Spawner {
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.spawned.lock().unwrap().push(Box::pin(fut));
+    }
+}
+
+impl std::fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Spawner").finish_non_exhaustive()
+    }
+}
+
+/// A deterministic, single-threaded scheduler with a virtual clock
+///
+/// Every time more than one task is ready to make progress, the simulation picks one with a PRNG
+/// seeded from [`Simulation::new`]. Given the same seed, and tasks which only communicate through
+/// black-box primitives and a [`SimTimer`], the interleaving replays exactly, so a failing seed
+/// can be reproduced by running it again.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::{*, testing::*};
+/// # use std::time::Duration;
+/// struct Remind;
+///
+/// #[derive(Default)]
+/// struct Alarm(bool);
+/// impl Actor for Alarm {}
+/// impl Handler<Remind> for Alarm {
+///     async fn handle(&mut self, _msg: Remind, ctx: &Context<Self>) {
+///         self.0 = true;
+///         ctx.shutdown();
+///     }
+/// }
+///
+/// let mut sim = Simulation::new(42);
+/// let (mut executor, addr) = Executor::new(Alarm::default());
+/// executor.set_timer(sim.timer());
+///
+/// sim.spawn(async move {
+///     addr.send_after(Remind, Duration::from_secs(3600)).await;
+///     executor.run().await.unwrap();
+///     assert!(executor.actor_ref().0);
+/// });
+///
+/// // An hour of virtual time passes instantly
+/// sim.run();
+/// assert_eq!(sim.elapsed(), Duration::from_secs(3600));
+/// ```
+pub struct Simulation {
+    seed: u64,
+    rng: Rng,
+    tasks: Vec<Option<(Task, Waker)>>,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+    spawned: Arc<Mutex<Vec<Task>>>,
+    clock: Arc<Mutex<Clock>>,
+}
+
+impl std::fmt::Debug for Simulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Simulation")
+            .field("seed", &self.seed)
+            .field("tasks", &self.tasks.iter().flatten().count())
+            .field("elapsed", &self.elapsed())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        let now = Instant::now();
+        let clock = Clock {
+            start: now,
+            now,
+            seq: 0,
+            sleepers: BinaryHeap::new(),
+        };
+
+        Self {
+            seed,
+            rng: Rng(seed),
+            tasks: Vec::new(),
+            ready: Default::default(),
+            spawned: Default::default(),
+            clock: Arc::new(Mutex::new(clock)),
+        }
+    }
+
+    /// The seed the simulation was created with, useful to log so a failure can be replayed
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A [`Timer`] backed by this simulation's virtual clock
+    pub fn timer(&self) -> SimTimer {
+        SimTimer {
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// A handle which can spawn tasks from within the simulation
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            spawned: self.spawned.clone(),
+        }
+    }
+
+    /// The amount of virtual time which has passed since the simulation was created
+    pub fn elapsed(&self) -> Duration {
+        let clock = self.clock.lock().unwrap();
+        clock.now - clock.start
+    }
+
+    pub fn spawn<F>(&mut self, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.insert(Box::pin(fut));
+    }
+
+    /// Runs every task until none can make progress, advancing the virtual clock whenever all
+    /// tasks are waiting on it
+    ///
+    /// Returns the number of tasks still pending, such as executors waiting on an empty mailbox.
+    pub fn run(&mut self) -> usize {
+        self.run_until(None)
+    }
+
+    /// As [`Self::run`], but stops once `duration` of virtual time has passed
+    pub fn run_for(&mut self, duration: Duration) -> usize {
+        let deadline = self.clock.lock().unwrap().now + duration;
+        self.run_until(Some(deadline))
+    }
+
+    fn run_until(&mut self, deadline: Option<Instant>) -> usize {
+        loop {
+            let spawned = std::mem::take(&mut *self.spawned.lock().unwrap());
+            for task in spawned {
+                self.insert(task);
+            }
+
+            let next = {
+                let ready = self.ready.lock().unwrap();
+                match ready.len() {
+                    0 => None,
+                    len => ready.iter().nth(self.rng.below(len)).copied(),
+                }
+            };
+
+            match next {
+                Some(id) => self.poll(id),
+                None => {
+                    let mut clock = self.clock.lock().unwrap();
+                    let within = |next: Instant| deadline.map_or(true, |deadline| next <= deadline);
+                    let due = clock.sleepers.peek().map(|Reverse(s)| s.deadline);
+                    if !due.is_some_and(within) || !clock.advance() {
+                        if let Some(deadline) = deadline {
+                            clock.now = clock.now.max(deadline);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.tasks.iter().flatten().count()
+    }
+
+    fn insert(&mut self, task: Task) {
+        let id = self.tasks.len();
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }));
+        self.tasks.push(Some((task, waker)));
+        self.ready.lock().unwrap().insert(id);
+    }
+
+    fn poll(&mut self, id: usize) {
+        self.ready.lock().unwrap().remove(&id);
+        let Some((task, waker)) = &mut self.tasks[id] else {
+            return;
+        };
+
+        let mut cx = Context::from_waker(waker);
+        if task.as_mut().poll(&mut cx).is_ready() {
+            self.tasks[id] = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Actor, Address, Context, Executor, Handler};
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<(&'static str, u32, Duration)>>>;
+
+    struct Ping(u32);
+
+    struct Node {
+        name: &'static str,
+        peer: Option<Address<Node>>,
+        log: Log,
+        timer: SimTimer,
+        start: Instant,
+    }
+
+    impl Actor for Node {}
+
+    impl Handler<Ping> for Node {
+        async fn handle(&mut self, msg: Ping, ctx: &Context<Self>) {
+            let elapsed = self.timer.now() - self.start;
+            self.log.lock().unwrap().push((self.name, msg.0, elapsed));
+            if msg.0 == 0 {
+                ctx.shutdown();
+                return;
+            }
+            if let Some(peer) = &self.peer {
+                peer.send_after(Ping(msg.0 - 1), Duration::from_millis(10))
+                    .await;
+            }
+        }
+    }
+
+    fn scenario(seed: u64) -> (Vec<(&'static str, u32, Duration)>, Duration) {
+        let mut sim = Simulation::new(seed);
+        let log = Log::default();
+        let start = sim.timer().now();
+        let node = |name| Node {
+            name,
+            peer: None,
+            log: log.clone(),
+            timer: sim.timer(),
+            start,
+        };
+
+        let (mut a, a_addr) = Executor::new(node("a"));
+        let (mut b, b_addr) = Executor::new(node("b"));
+        let (mut c, c_addr) = Executor::new(node("c"));
+        a.actor_mut().peer = Some(b_addr.clone());
+        b.actor_mut().peer = Some(a_addr.clone());
+        for executor in [&mut a, &mut b, &mut c] {
+            executor.set_timer(sim.timer());
+        }
+
+        sim.spawn(async move {
+            let _ = a.run().await;
+        });
+        sim.spawn(async move {
+            let _ = b.run().await;
+        });
+        sim.spawn(async move {
+            let _ = c.run().await;
+        });
+        sim.spawner().spawn(async move {
+            a_addr.send(Ping(5)).await;
+            c_addr.send(Ping(0)).await;
+        });
+
+        sim.run();
+        let log = log.lock().unwrap().clone();
+        (log, sim.elapsed())
+    }
+
+    #[test]
+    fn same_seed_replays_exactly() {
+        for seed in 0..16 {
+            assert_eq!(scenario(seed), scenario(seed));
+        }
+    }
+
+    #[test]
+    fn different_seeds_interleave_differently() {
+        let runs: Vec<_> = (0..16).map(|seed| scenario(seed).0).collect();
+        assert!(runs.iter().any(|run| *run != runs[0]));
+    }
+
+    #[test]
+    fn virtual_time_advances_without_waiting() {
+        let real = Instant::now();
+        let (log, elapsed) = scenario(7);
+
+        assert_eq!(elapsed, Duration::from_millis(50));
+        assert!(real.elapsed() < Duration::from_secs(1));
+        let pings: Vec<_> = log.iter().filter(|(name, ..)| *name != "c").collect();
+        assert_eq!(pings.len(), 6);
+        assert_eq!(pings[5].2, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn run_for_stops_at_deadline() {
+        let mut sim = Simulation::new(1);
+        let timer = sim.timer();
+        let done = Arc::new(Mutex::new(false));
+        let flag = done.clone();
+        sim.spawn(async move {
+            timer.sleep(Duration::from_secs(10)).await;
+            *flag.lock().unwrap() = true;
+        });
+
+        assert_eq!(sim.run_for(Duration::from_secs(5)), 1);
+        assert_eq!(sim.elapsed(), Duration::from_secs(5));
+        assert!(!*done.lock().unwrap());
+
+        assert_eq!(sim.run(), 0);
+        assert!(*done.lock().unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, OnceLock,
+    },
+    task::{Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// A boxed sleep future returned by a [`Timer`]
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A source of time for the executor
+///
+/// Black-box does not ship with a runtime, so anything that needs to wait on the clock goes through
+/// this trait. Implement it on top of your runtime's timer (e.g. `tokio::time::sleep_until`) to
+/// avoid the background thread used by the default [`ThreadTimer`].
+pub trait Timer: Send + Sync + 'static {
+    /// The current instant according to this timer
+    fn now(&self) -> Instant;
+
+    /// Returns a future which resolves once `deadline` has been reached
+    fn sleep_until(&self, deadline: Instant) -> Sleep;
+
+    /// Returns a future which resolves once `duration` has elapsed
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// A cheaply cloneable, type-erased [`Timer`]
+#[derive(Clone)]
+pub(crate) struct SharedTimer(Arc<dyn Timer>);
+
+impl SharedTimer {
+    pub(crate) fn new<T: Timer>(timer: T) -> Self {
+        Self(Arc::new(timer))
+    }
+}
+
+impl Default for SharedTimer {
+    fn default() -> Self {
+        Self::new(ThreadTimer)
+    }
+}
+
+impl std::fmt::Debug for SharedTimer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedTimer").finish_non_exhaustive()
+    }
+}
+
+impl std::ops::Deref for SharedTimer {
+    type Target = dyn Timer;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+/// The default [`Timer`], driven by a single shared background thread
+///
+/// The thread is lazily started the first time a sleep is actually awaited, so executors which
+/// never wait on the clock never spawn it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadTimer;
+
+impl Timer for ThreadTimer {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Box::pin(ThreadSleep {
+            id: SLEEP_ID.fetch_add(1, Ordering::Relaxed),
+            deadline,
+            registered: None,
+        })
+    }
+}
+
+/// Identifies each sleep registered with the timer thread, so it can be removed once dropped
+static SLEEP_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+struct Wheel {
+    /// Keyed by deadline, then id, so the earliest deadline comes first
+    entries: Mutex<BTreeMap<(Instant, u64), Waker>>,
+    condvar: Condvar,
+}
+
+impl Wheel {
+    fn get() -> &'static Arc<Wheel> {
+        static WHEEL: OnceLock<Arc<Wheel>> = OnceLock::new();
+        WHEEL.get_or_init(|| {
+            let wheel = Arc::new(Wheel::default());
+            let thread_wheel = wheel.clone();
+            std::thread::Builder::new()
+                .name("black-box-timer".into())
+                .spawn(move || thread_wheel.drive())
+                .expect("failed to spawn timer thread");
+            wheel
+        })
+    }
+
+    /// Register the waker for a sleep, replacing any it registered before
+    fn register(&self, deadline: Instant, id: u64, waker: Waker) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((deadline, id), waker);
+        self.condvar.notify_one();
+    }
+
+    fn deregister(&self, deadline: Instant, id: u64) {
+        self.entries.lock().unwrap().remove(&(deadline, id));
+    }
+
+    fn drive(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while entries
+                .first_key_value()
+                .is_some_and(|(&(deadline, _), _)| deadline <= now)
+            {
+                let (_, waker) = entries.pop_first().unwrap();
+                waker.wake();
+            }
+
+            entries = match entries.first_key_value() {
+                Some((&(deadline, _), _)) => {
+                    let wait = deadline.saturating_duration_since(now);
+                    self.condvar.wait_timeout(entries, wait).unwrap().0
+                }
+                None => self.condvar.wait(entries).unwrap(),
+            };
+        }
+    }
+}
+
+struct ThreadSleep {
+    id: u64,
+    deadline: Instant,
+    registered: Option<Waker>,
+}
+
+impl Drop for ThreadSleep {
+    fn drop(&mut self) {
+        // A sleep dropped before its deadline must not keep its waker alive on the timer thread
+        if self.registered.is_some() && Instant::now() < self.deadline {
+            Wheel::get().deregister(self.deadline, self.id);
+        }
+    }
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        // Only hand the waker to the timer thread when it changes, repeated polls would otherwise
+        // wake the task needlessly
+        let this = self.get_mut();
+        if !this
+            .registered
+            .as_ref()
+            .is_some_and(|waker| waker.will_wake(cx.waker()))
+        {
+            this.registered = Some(cx.waker().clone());
+            Wheel::get().register(this.deadline, this.id, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A [`Timer`] whose clock only moves when advanced by the test
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct ManualTimer(Arc<Mutex<(Instant, Vec<Waker>)>>);
+
+#[cfg(test)]
+impl ManualTimer {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Mutex::new((Instant::now(), Vec::new()))))
+    }
+
+    /// Move the clock forward, waking every sleep to check its deadline
+    pub(crate) fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut state = self.0.lock().unwrap();
+            state.0 += duration;
+            std::mem::take(&mut state.1)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+#[cfg(test)]
+impl Timer for ManualTimer {
+    fn now(&self) -> Instant {
+        self.0.lock().unwrap().0
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let state = self.0.clone();
+        Box::pin(std::future::poll_fn(move |cx| {
+            let mut state = state.lock().unwrap();
+            if state.0 >= deadline {
+                return Poll::Ready(());
+            }
+            state.1.push(cx.waker().clone());
+            Poll::Pending
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn thread_timer_sleeps() {
+        let start = Instant::now();
+        ThreadTimer.sleep(Duration::from_millis(50)).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn thread_timer_orders_deadlines() {
+        let timer = ThreadTimer;
+        let long = timer.sleep(Duration::from_millis(200));
+        let short = async {
+            timer.sleep(Duration::from_millis(20)).await;
+            true
+        };
+        let result = crate::futures::race_biased(
+            async {
+                long.await;
+                false
+            },
+            short,
+        )
+        .await;
+        assert!(result);
+    }
+
+    #[tokio::test]
+    async fn dropped_sleeps_are_deregistered() {
+        let key = (Instant::now() + Duration::from_secs(60), u64::MAX);
+        let mut sleep = ThreadSleep {
+            id: key.1,
+            deadline: key.0,
+            registered: None,
+        };
+        let poll = std::future::poll_fn(|cx| Poll::Ready(Pin::new(&mut sleep).poll(cx))).await;
+        assert!(poll.is_pending());
+
+        let registered = || Wheel::get().entries.lock().unwrap().contains_key(&key);
+        assert!(registered());
+        drop(sleep);
+        assert!(!registered());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::AskError,
+    message::Message,
+    request::{Request, RequestHandler},
+    Actor, ActorId, Address, Handler, ScheduleHandle,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A tuple of the message types a [`View`] of `A` may send
+///
+/// Any message types may be listed, whether `A` handles them is checked by the [`View`] method
+/// used to send one, which requires a [`Handler`] or [`RequestHandler`] for it. This is
+/// implemented for tuples of up to 8 message types, and cannot be implemented outside of
+/// this crate.
+pub trait Permits<A>: sealed::Sealed {}
+
+/// The message type `M` is one of those listed in the tuple, at position `I`
+///
+/// `I` is inferred, and only exists so that each position of a tuple can be implemented
+/// separately.
+pub trait Allows<M, I>: sealed::Sealed {}
+
+/// The position of a message type in a [`Permits`] tuple
+#[derive(Debug)]
+pub struct Position<const N: usize>(());
+
+/// An address which can only send the message types listed in `P`
+///
+/// Constructed with [`Address::view`], a view can be handed to less trusted code without giving it
+/// access to every message the actor handles.
+///
+/// # Example
+///
+/// ```
+/// # use black_box::*;
+/// struct Read;
+/// struct Subscribe;
+/// struct AdminReset;
+///
+/// struct Store;
+/// impl Actor for Store {}
+/// impl Handler<Read> for Store {
+///     async fn handle(&mut self, _msg: Read, _ctx: &Context<Self>) {}
+/// }
+/// impl Handler<Subscribe> for Store {
+///     async fn handle(&mut self, _msg: Subscribe, _ctx: &Context<Self>) {}
+/// }
+/// impl Handler<AdminReset> for Store {
+///     async fn handle(&mut self, _msg: AdminReset, _ctx: &Context<Self>) {}
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (executor, address) = Executor::new(Store);
+/// let plugin = address.view::<(Read, Subscribe)>();
+/// plugin.send(Read).await;
+/// plugin.send(Subscribe).await;
+/// # }
+/// ```
+///
+/// Messages which are not listed cannot be sent:
+///
+/// ```compile_fail
+/// # use black_box::*;
+/// # struct Read;
+/// # struct AdminReset;
+/// # struct Store;
+/// # impl Actor for Store {}
+/// # impl Handler<Read> for Store {
+/// #     async fn handle(&mut self, _msg: Read, _ctx: &Context<Self>) {}
+/// # }
+/// # impl Handler<AdminReset> for Store {
+/// #     async fn handle(&mut self, _msg: AdminReset, _ctx: &Context<Self>) {}
+/// # }
+/// # async fn example(address: Address<Store>) {
+/// let plugin = address.view::<(Read,)>();
+/// plugin.send(AdminReset).await;
+/// # }
+/// ```
+pub struct View<A, P> {
+    address: Address<A>,
+    permits: PhantomData<fn() -> P>,
+}
+
+impl<A, P> std::fmt::Debug for View<A, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("View")
+            .field("id", &self.address.id())
+            .field("permits", &std::any::type_name::<P>())
+            .finish()
+    }
+}
+
+impl<A, P> Clone for View<A, P> {
+    fn clone(&self) -> Self {
+        Self {
+            address: self.address.clone(),
+            permits: PhantomData,
+        }
+    }
+}
+
+impl<A> Address<A> {
+    /// A view of this address which can only send the message types listed in `P`
+    pub fn view<P: Permits<A>>(&self) -> View<A, P> {
+        View {
+            address: self.clone(),
+            permits: PhantomData,
+        }
+    }
+}
+
+impl<A, P> View<A, P> {
+    /// The id of the actor this view sends to
+    pub fn id(&self) -> ActorId {
+        self.address.id()
+    }
+
+    /// See [`Address::is_closed`]
+    pub fn is_closed(&self) -> bool {
+        self.address.is_closed()
+    }
+}
+
+impl<A, P> View<A, P>
+where
+    A: 'static + Actor + Send,
+{
+    /// See [`Address::send`]
+    pub async fn send<M, I>(&self, message: M)
+    where
+        P: Allows<M, I>,
+        A: Handler<M>,
+        M: Message,
+    {
+        self.address.send(message).await
+    }
+
+    /// See [`Address::send_blocking`]
+    pub fn send_blocking<M, I>(&self, message: M)
+    where
+        P: Allows<M, I>,
+        A: Handler<M>,
+        M: Message,
+    {
+        self.address.send_blocking(message)
+    }
+
+    /// See [`Address::try_send`]
+    pub fn try_send<M, I>(&self, message: M)
+    where
+        P: Allows<M, I>,
+        A: Handler<M>,
+        M: Message,
+    {
+        self.address.try_send(message)
+    }
+
+    /// See [`Address::ask`]
+    pub async fn ask<M, I>(&self, message: M) -> Result<M::Response, AskError>
+    where
+        P: Allows<M, I>,
+        A: RequestHandler<M>,
+        M: Request,
+    {
+        self.address.ask(message).await
+    }
+
+    /// See [`Address::ask_timeout`]
+    pub async fn ask_timeout<M, I>(
+        &self,
+        message: M,
+        timeout: Duration,
+    ) -> Result<M::Response, AskError>
+    where
+        P: Allows<M, I>,
+        A: RequestHandler<M>,
+        M: Request,
+    {
+        self.address.ask_timeout(message, timeout).await
+    }
+
+    /// See [`Address::send_after`]
+    pub async fn send_after<M, I>(&self, message: M, delay: Duration) -> ScheduleHandle
+    where
+        P: Allows<M, I>,
+        A: Handler<M>,
+        M: Message,
+    {
+        self.address.send_after(message, delay).await
+    }
+
+    /// See [`Address::send_at`]
+    pub async fn send_at<M, I>(&self, message: M, deadline: Instant) -> ScheduleHandle
+    where
+        P: Allows<M, I>,
+        A: Handler<M>,
+        M: Message,
+    {
+        self.address.send_at(message, deadline).await
+    }
+}
+
+macro_rules! permits {
+    ($($m:ident $i:tt)*) => {
+        impl<$($m),*> sealed::Sealed for ($($m,)*) {}
+
+        impl<A, $($m),*> Permits<A> for ($($m,)*)
+        where
+            $($m: Message,)*
+        {
+        }
+
+        permits!(@allows [$($m)*] $($m $i)*);
+    };
+    // Each position is implemented in turn, as the full list cannot be repeated within a
+    // repetition over its own elements
+    (@allows [$($all:ident)*]) => {};
+    (@allows [$($all:ident)*] $m:ident $i:tt $($rest:tt)*) => {
+        impl<$($all),*> Allows<$m, Position<$i>> for ($($all,)*) {}
+
+        permits!(@allows [$($all)*] $($rest)*);
+    };
+}
+
+permits!(M0 0);
+permits!(M0 0 M1 1);
+permits!(M0 0 M1 1 M2 2);
+permits!(M0 0 M1 1 M2 2 M3 3);
+permits!(M0 0 M1 1 M2 2 M3 3 M4 4);
+permits!(M0 0 M1 1 M2 2 M3 3 M4 4 M5 5);
+permits!(M0 0 M1 1 M2 2 M3 3 M4 4 M5 5 M6 6);
+permits!(M0 0 M1 1 M2 2 M3 3 M4 4 M5 5 M6 6 M7 7);
+
+#[cfg(test)]
+mod tests {
+    use crate::{Context, Executor};
+
+    use super::*;
+
+    struct Get;
+    struct Set(u32);
+    struct Reset;
+
+    impl Request for Get {
+        type Response = u32;
+    }
+
+    #[derive(Default)]
+    struct Cell(u32);
+    impl Actor for Cell {}
+
+    impl RequestHandler<Get> for Cell {
+        async fn respond(&mut self, _msg: Get, _ctx: &Context<Self>) -> u32 {
+            self.0
+        }
+    }
+
+    impl Handler<Set> for Cell {
+        async fn handle(&mut self, msg: Set, _ctx: &Context<Self>) {
+            self.0 = msg.0;
+        }
+    }
+
+    impl Handler<Reset> for Cell {
+        async fn handle(&mut self, _msg: Reset, _ctx: &Context<Self>) {
+            self.0 = 0;
+        }
+    }
+
+    #[tokio::test]
+    async fn view_sends_permitted_messages() {
+        let (mut executor, address) = Executor::new(Cell::default());
+        let view = address.view::<(Set, Get)>();
+        assert_eq!(view.id(), address.id());
+        drop(address);
+
+        let run = tokio::spawn(async move { executor.run().await });
+        view.send(Set(3)).await;
+        assert_eq!(view.ask(Get).await, Ok(3));
+
+        drop(view);
+        assert!(run.await.unwrap().is_err());
+    }
+}